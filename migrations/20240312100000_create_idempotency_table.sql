@@ -0,0 +1,16 @@
+CREATE TYPE header_pair AS (
+   name TEXT,
+   value BYTEA
+);
+
+CREATE TABLE idempotency (
+   caller TEXT NOT NULL,
+   idempotency_key TEXT NOT NULL,
+   response_status_code SMALLINT,
+   response_headers header_pair[],
+   response_body BYTEA,
+   created_at timestamptz NOT NULL,
+   PRIMARY KEY(caller, idempotency_key)
+);
+
+CREATE INDEX idempotency_created_at_idx ON idempotency (created_at);
@@ -0,0 +1,2 @@
+-- Bumped on every password change: sessions created before the bump are rejected.
+ALTER TABLE users ADD COLUMN session_generation INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE subscription_tokens ADD COLUMN used_at timestamptz NULL;
@@ -0,0 +1,10 @@
+CREATE TABLE email_events (
+   id uuid PRIMARY KEY,
+   record_type TEXT NOT NULL,
+   email TEXT NOT NULL,
+   message_id TEXT NULL,
+   payload JSONB NOT NULL,
+   occurred_at timestamptz NULL,
+   received_at timestamptz NOT NULL
+);
+CREATE INDEX email_events_email_idx ON email_events (email);
@@ -0,0 +1,5 @@
+CREATE TABLE suppressed_addresses (
+   email TEXT NOT NULL PRIMARY KEY,
+   reason TEXT NOT NULL,
+   suppressed_at timestamptz NOT NULL
+);
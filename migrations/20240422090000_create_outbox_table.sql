@@ -0,0 +1,13 @@
+CREATE TABLE outbox (
+   id uuid PRIMARY KEY,
+   recipient TEXT NOT NULL,
+   subject TEXT NOT NULL,
+   html_content TEXT NOT NULL,
+   text_content TEXT NOT NULL,
+   created_at timestamptz NOT NULL DEFAULT now(),
+   n_attempts SMALLINT NOT NULL DEFAULT 0,
+   execute_after timestamptz NOT NULL DEFAULT now(),
+   sent_at timestamptz NULL,
+   failed_at timestamptz NULL
+);
+CREATE INDEX outbox_pending_idx ON outbox (execute_after) WHERE sent_at IS NULL AND failed_at IS NULL;
@@ -0,0 +1,3 @@
+-- A hash of the request that claimed the key, so the key can't be reused for another one.
+-- Keys saved before this column existed have none and are replayed as before.
+ALTER TABLE idempotency ADD COLUMN request_hash BYTEA;
@@ -0,0 +1,70 @@
+use std::ops::Deref;
+
+use actix_web::{
+	body::MessageBody,
+	dev::{ServiceRequest, ServiceResponse},
+	error::InternalError,
+	web, FromRequest, HttpMessage,
+};
+use actix_web_lab::middleware::Next;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use super::get_session_generation;
+use crate::{
+	session_state::TypedSession,
+	utils::{e500, see_other},
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(Uuid);
+
+impl std::fmt::Display for UserId {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+impl Deref for UserId {
+	type Target = Uuid;
+
+	fn deref(&self) -> &Self::Target {
+		&self.0
+	}
+}
+
+pub async fn reject_anonymous_users(
+	mut req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+	let session = {
+		let (http_request, payload) = req.parts_mut();
+		TypedSession::from_request(http_request, payload).await
+	}?;
+
+	let user_id = match session.get_user_id().map_err(e500)? {
+		Some(user_id) => user_id,
+		None => {
+			let response = see_other("/login");
+			let e = anyhow::anyhow!("The user has not logged in");
+			return Err(InternalError::from_response(e, response).into());
+		}
+	};
+
+	// Sessions opened before the latest password change are no longer valid.
+	let connection_pool = req
+		.app_data::<web::Data<Pool<Postgres>>>()
+		.expect("The connection pool is not registered as application data.");
+	let current_generation = get_session_generation(user_id, connection_pool)
+		.await
+		.map_err(e500)?;
+	if current_generation.is_none() || current_generation != session.get_session_generation().map_err(e500)? {
+		session.log_out();
+		let response = see_other("/login");
+		let e = anyhow::anyhow!("The session has been invalidated");
+		return Err(InternalError::from_response(e, response).into());
+	}
+
+	req.extensions_mut().insert(UserId(user_id));
+	next.call(req).await
+}
@@ -0,0 +1,166 @@
+use anyhow::Context;
+use argon2::{
+	password_hash::SaltString,
+	Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version,
+};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::telemetry::spawn_blocking_with_tracing;
+
+/// Bounds, in characters, for the passwords admins choose.
+pub const MIN_PASSWORD_LENGTH: usize = 12;
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuthError {
+	#[error("Invalid credentials.")]
+	InvalidCredentials(#[source] anyhow::Error),
+	#[error(transparent)]
+	UnexpectedError(#[from] anyhow::Error),
+}
+
+pub struct Credentials {
+	pub username: String,
+	pub password: Secret<String>,
+}
+
+#[tracing::instrument(
+	name = "Get stored credentials",
+	skip(username, connection_pool)
+)]
+async fn get_stored_credentials(
+	username: &str,
+	connection_pool: &Pool<Postgres>,
+) -> Result<Option<(Uuid, Secret<String>)>, anyhow::Error> {
+	let row = sqlx::query!(
+		r#"
+		SELECT user_id, password_hash
+		FROM users
+		WHERE username = $1
+		"#,
+		username,
+	)
+	.fetch_optional(connection_pool)
+	.await
+	.context("Failed to perform a query to retrieve stored credentials.")?
+	.map(|row| (row.user_id, Secret::new(row.password_hash)));
+	Ok(row)
+}
+
+#[tracing::instrument(
+	name = "Validate credentials",
+	skip(credentials, connection_pool)
+)]
+pub async fn validate_credentials(
+	credentials: Credentials,
+	connection_pool: &Pool<Postgres>,
+) -> Result<Uuid, AuthError> {
+	let mut user_id = None;
+	// Verify against a dummy hash when the username is unknown, so that both
+	// branches take the same time and usernames can't be enumerated.
+	let mut expected_password_hash = Secret::new(
+		"$argon2id$v=19$m=15000,t=2,p=1$\
+		gZiV/M1gPc22ElAH/Jh1Hw$\
+		CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
+			.to_string(),
+	);
+
+	if let Some((stored_user_id, stored_password_hash)) =
+		get_stored_credentials(&credentials.username, connection_pool).await?
+	{
+		user_id = Some(stored_user_id);
+		expected_password_hash = stored_password_hash;
+	}
+
+	spawn_blocking_with_tracing(move || {
+		verify_password_hash(expected_password_hash, credentials.password)
+	})
+	.await
+	.context("Failed to spawn blocking task.")??;
+
+	user_id
+		.ok_or_else(|| anyhow::anyhow!("Unknown username."))
+		.map_err(AuthError::InvalidCredentials)
+}
+
+#[tracing::instrument(
+	name = "Verify password hash",
+	skip(expected_password_hash, password_candidate)
+)]
+fn verify_password_hash(
+	expected_password_hash: Secret<String>,
+	password_candidate: Secret<String>,
+) -> Result<(), AuthError> {
+	let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+		.context("Failed to parse hash in PHC string format.")?;
+
+	Argon2::default()
+		.verify_password(
+			password_candidate.expose_secret().as_bytes(),
+			&expected_password_hash,
+		)
+		.context("Invalid password.")
+		.map_err(AuthError::InvalidCredentials)
+}
+
+#[tracing::instrument(
+	name = "Change password",
+	skip(password, connection_pool)
+)]
+pub async fn change_password(
+	user_id: Uuid,
+	password: Secret<String>,
+	connection_pool: &Pool<Postgres>,
+) -> Result<i32, anyhow::Error> {
+	let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+		.await?
+		.context("Failed to hash password")?;
+	let session_generation = sqlx::query_scalar!(
+		r#"
+		UPDATE users
+		SET
+			password_hash = $1,
+			session_generation = session_generation + 1
+		WHERE user_id = $2
+		RETURNING session_generation
+		"#,
+		password_hash.expose_secret(),
+		user_id
+	)
+	.fetch_one(connection_pool)
+	.await
+	.context("Failed to change user's password in the database.")?;
+	Ok(session_generation)
+}
+
+#[tracing::instrument(
+	name = "Get session generation",
+	skip(connection_pool)
+)]
+pub async fn get_session_generation(
+	user_id: Uuid,
+	connection_pool: &Pool<Postgres>,
+) -> Result<Option<i32>, anyhow::Error> {
+	let session_generation = sqlx::query_scalar!(
+		"SELECT session_generation FROM users WHERE user_id = $1",
+		user_id
+	)
+	.fetch_optional(connection_pool)
+	.await
+	.context("Failed to retrieve the session generation.")?;
+	Ok(session_generation)
+}
+
+pub fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+	let salt = SaltString::generate(&mut rand::thread_rng());
+	let password_hash = Argon2::new(
+		Algorithm::Argon2id,
+		Version::V0x13,
+		Params::new(15000, 2, 1, None).unwrap(),
+	)
+	.hash_password(password.expose_secret().as_bytes(), &salt)?
+	.to_string();
+	Ok(Secret::new(password_hash))
+}
@@ -0,0 +1,115 @@
+//! Command-line interface of the `zero2prod` binary.
+//!
+//! `serve` runs the API and its background workers; the other commands are one-off
+//! operations tasks run against the same configuration.
+mod subscribers;
+mod users;
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+use sqlx::{Connection, PgConnection};
+
+use crate::{
+	configuration::DatabaseSettings, domain::SubscriberEmail, email_client::EmailClient, migrations::run_migrations,
+};
+
+pub use subscribers::{export_subscribers, import_subscribers, list_subscribers, ImportReport, SubscriberRecord};
+pub use users::{create_admin, read_password};
+
+#[derive(clap::Parser)]
+#[command(name = "zero2prod", version, about = "A newsletter API and its operations tooling.")]
+pub struct Cli {
+	/// Directory holding `base.yaml` and the environment files [default: ./configuration]
+	#[arg(long, global = true, env = "APP_CONFIGURATION_DIRECTORY")]
+	pub config_dir: Option<PathBuf>,
+	/// Runs `serve` if omitted.
+	#[command(subcommand)]
+	pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+	/// Run the API, the newsletter delivery worker and the outbox dispatcher.
+	Serve,
+	/// Create the database if it does not exist and apply pending migrations.
+	Migrate,
+	/// Inspect the configuration.
+	#[command(subcommand)]
+	Config(ConfigCommand),
+	/// Manage subscribers.
+	#[command(subcommand)]
+	Subscribers(SubscribersCommand),
+	/// Manage admins.
+	#[command(subcommand)]
+	Users(UsersCommand),
+	/// Send an email to ADDRESS through the configured transports.
+	SendTestEmail { address: String },
+}
+
+#[derive(clap::Subcommand)]
+pub enum UsersCommand {
+	/// Create an admin called USERNAME, reading their password from stdin or PASSWORD_FILE.
+	CreateAdmin {
+		username: String,
+		#[arg(long, env = "APP_ADMIN_PASSWORD_FILE")]
+		password_file: Option<PathBuf>,
+	},
+}
+
+#[derive(clap::Subcommand)]
+pub enum ConfigCommand {
+	/// Validate the configuration and print it, secrets redacted.
+	Check,
+}
+
+#[derive(clap::Subcommand)]
+pub enum SubscribersCommand {
+	/// Print every subscriber.
+	List,
+	/// Write every subscriber as CSV.
+	Export {
+		/// Write to this file instead of stdout.
+		#[arg(long, short)]
+		output: Option<PathBuf>,
+	},
+	/// Add the rows of a CSV file with `email` and `name` columns as confirmed subscribers.
+	Import { file: PathBuf },
+}
+
+#[tracing::instrument(name = "Creating and migrating the database", skip_all, fields(database_name = %config.database_name))]
+pub async fn migrate(config: &DatabaseSettings) -> Result<(), anyhow::Error> {
+	let mut connection = PgConnection::connect_with(&config.without_db())
+		.await
+		.context("Failed to connect to Postgres.")?;
+	let exists = sqlx::query_scalar!(
+		r#"SELECT EXISTS(SELECT 1 FROM pg_database WHERE datname = $1) AS "exists!""#,
+		config.database_name
+	)
+	.fetch_one(&mut connection)
+	.await
+	.context("Failed to look up the database.")?;
+	if !exists {
+		sqlx::query(&format!(r#"CREATE DATABASE "{}";"#, config.database_name.replace('"', r#""""#)))
+			.execute(&mut connection)
+			.await
+			.context("Failed to create the database.")?;
+	}
+	run_migrations(config).await?;
+	Ok(())
+}
+
+#[tracing::instrument(name = "Sending a test email", skip(email_client))]
+pub async fn send_test_email(email_client: &EmailClient, address: &str) -> Result<(), anyhow::Error> {
+	let recipient = SubscriberEmail::parse(address.to_owned()).map_err(anyhow::Error::msg)?;
+	email_client
+		.send_email(
+			&recipient,
+			"zero2prod test email",
+			"<p>This is a test email sent by <code>zero2prod send-test-email</code>.</p>",
+			"This is a test email sent by `zero2prod send-test-email`.",
+		)
+		.await
+		.context("Failed to send the test email.")?;
+	Ok(())
+}
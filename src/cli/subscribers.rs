@@ -0,0 +1,137 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{
+	domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+	suppression::suppressed_among,
+};
+
+/// A subscriber as listed and exported; imports only read `email` and `name`.
+#[derive(Debug, serde::Serialize)]
+pub struct SubscriberRecord {
+	pub email: String,
+	pub name: String,
+	pub status: String,
+	pub subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+struct ImportRow {
+	email: String,
+	name: String,
+}
+
+/// What `import_subscribers` did with each row of the file.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+	pub imported: usize,
+	pub already_subscribed: usize,
+	pub suppressed: usize,
+	/// The line number and reason of every row that could not be imported.
+	pub invalid: Vec<(u64, String)>,
+}
+
+#[tracing::instrument(name = "Listing subscribers", skip_all)]
+pub async fn list_subscribers(connection_pool: &Pool<Postgres>) -> Result<Vec<SubscriberRecord>, anyhow::Error> {
+	let subscribers = sqlx::query_as!(
+		SubscriberRecord,
+		"SELECT email, name, status, subscribed_at FROM subscriptions ORDER BY subscribed_at, email"
+	)
+	.fetch_all(connection_pool)
+	.await
+	.context("Failed to fetch the subscribers.")?;
+	Ok(subscribers)
+}
+
+/// Writes every subscriber as CSV with a header row, returning how many were written.
+#[tracing::instrument(name = "Exporting subscribers", skip_all)]
+pub async fn export_subscribers(
+	connection_pool: &Pool<Postgres>,
+	writer: impl std::io::Write,
+) -> Result<usize, anyhow::Error> {
+	let subscribers = list_subscribers(connection_pool).await?;
+	let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(writer);
+	// Written up front rather than by `serialize`, so an empty export still has its header.
+	writer
+		.write_record(["email", "name", "status", "subscribed_at"])
+		.context("Failed to write the CSV header row.")?;
+	for subscriber in &subscribers {
+		writer.serialize(subscriber).context("Failed to write a subscriber.")?;
+	}
+	writer.flush().context("Failed to write the subscribers.")?;
+	Ok(subscribers.len())
+}
+
+/// Adds the subscribers of a CSV file with `email` and `name` columns, in a single
+/// transaction. They are imported as confirmed: they opted in wherever they come from.
+///
+/// Existing subscribers are left untouched, suppressed addresses and invalid rows are skipped.
+#[tracing::instrument(name = "Importing subscribers", skip_all)]
+pub async fn import_subscribers(
+	connection_pool: &Pool<Postgres>,
+	reader: impl std::io::Read,
+) -> Result<ImportReport, anyhow::Error> {
+	let mut report = ImportReport::default();
+	let mut transaction = connection_pool
+		.begin()
+		.await
+		.context("Failed to acquire a Postgres connection from the pool.")?;
+	let mut reader = csv::Reader::from_reader(reader);
+	let headers = reader.headers().context("Failed to read the CSV header row.")?.clone();
+	let mut record = csv::StringRecord::new();
+	let mut subscribers = Vec::new();
+	while reader.read_record(&mut record).context("Failed to read the CSV file.")? {
+		let line = record.position().map_or(0, |position| position.line());
+		let subscriber = match record.deserialize(Some(&headers)) {
+			Ok(row) => parse_row(row),
+			Err(e) => Err(e.to_string()),
+		};
+		match subscriber {
+			Ok(subscriber) => subscribers.push(subscriber),
+			Err(reason) => report.invalid.push((line, reason)),
+		}
+	}
+	let emails: Vec<_> = subscribers.iter().map(|subscriber| subscriber.email.as_ref().to_owned()).collect();
+	let suppressed = suppressed_among(&mut *transaction, &emails)
+		.await
+		.context("Failed to check the addresses against the suppression list.")?;
+	for subscriber in subscribers {
+		if suppressed.contains(&subscriber.email.as_ref().to_lowercase()) {
+			report.suppressed += 1;
+			continue;
+		}
+		let inserted = sqlx::query!(
+			r#"
+			INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+			VALUES ($1, $2, $3, now(), 'confirmed')
+			ON CONFLICT (email) DO NOTHING
+			"#,
+			Uuid::new_v4(),
+			subscriber.email.as_ref(),
+			subscriber.name.as_ref(),
+		)
+		.execute(&mut *transaction)
+		.await
+		.context("Failed to insert a subscriber.")?
+		.rows_affected();
+		if inserted == 0 {
+			report.already_subscribed += 1;
+		} else {
+			report.imported += 1;
+		}
+	}
+	transaction
+		.commit()
+		.await
+		.context("Failed to commit the imported subscribers.")?;
+	Ok(report)
+}
+
+fn parse_row(row: ImportRow) -> Result<NewSubscriber, String> {
+	Ok(NewSubscriber {
+		email: SubscriberEmail::parse(row.email)?,
+		name: SubscriberName::parse(row.name)?,
+	})
+}
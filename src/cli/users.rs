@@ -0,0 +1,71 @@
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{
+	authentication::{compute_password_hash, MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH},
+	telemetry::spawn_blocking_with_tracing,
+};
+
+/// Creates an admin who can log in with `username` and `password`, returning their id.
+///
+/// There is no default account: run this once against a fresh database.
+#[tracing::instrument(name = "Creating an admin", skip(connection_pool, password))]
+pub async fn create_admin(
+	connection_pool: &Pool<Postgres>,
+	username: &str,
+	password: Secret<String>,
+) -> Result<Uuid, anyhow::Error> {
+	let username = username.trim();
+	if username.is_empty() {
+		anyhow::bail!("The username must not be empty.");
+	}
+	let password_length = password.expose_secret().chars().count();
+	if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&password_length) {
+		anyhow::bail!(
+			"The password must be between {} and {} characters long.",
+			MIN_PASSWORD_LENGTH,
+			MAX_PASSWORD_LENGTH
+		);
+	}
+	let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+		.await?
+		.context("Failed to hash the password.")?;
+	let user_id = sqlx::query_scalar!(
+		r#"
+		INSERT INTO users (user_id, username, password_hash)
+		VALUES ($1, $2, $3)
+		ON CONFLICT (username) DO NOTHING
+		RETURNING user_id
+		"#,
+		Uuid::new_v4(),
+		username,
+		password_hash.expose_secret()
+	)
+	.fetch_optional(connection_pool)
+	.await
+	.context("Failed to store the admin.")?
+	.with_context(|| format!("There is already a user called {}.", username))?;
+	Ok(user_id)
+}
+
+/// Reads a password from a file, such as a mounted secret, or the first line of stdin,
+/// dropping the trailing line break either way.
+pub fn read_password(file: Option<&std::path::Path>) -> Result<Secret<String>, anyhow::Error> {
+	let mut password = match file {
+		Some(path) => {
+			std::fs::read_to_string(path).with_context(|| format!("Failed to read {}.", path.display()))?
+		}
+		None => {
+			let mut line = String::new();
+			std::io::stdin()
+				.read_line(&mut line)
+				.context("Failed to read the password from stdin.")?;
+			line
+		}
+	};
+	let trimmed_length = password.trim_end_matches(['\r', '\n']).len();
+	password.truncate(trimmed_length);
+	Ok(Secret::new(password))
+}
@@ -1,26 +1,114 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
 use config::Config;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::{PgConnectOptions, PgSslMode}, ConnectOptions};
 
 use crate::domain::SubscriberEmail;
+use crate::email_client::{
+	CircuitBreakerPolicy, EmailClient, EmailTransport, FileTransport, PostmarkTransport, RateLimiter, RetryPolicy,
+	SmtpTransport,
+};
+use crate::utils::error_chain_fmt;
+
+const ENV_PREFIX: &str = "APP";
+const ENV_SEPARATOR: &str = "__";
+/// Suffix of the environment variables naming a file to read a setting from.
+const ENV_FILE_SUFFIX: &str = "_FILE";
+/// `cookie::Key::from` panics on anything shorter.
+const MIN_HMAC_SECRET_BYTES: usize = 64;
 
-#[derive(serde::Deserialize,Clone)]
+/// Serializes secrets as a placeholder, so settings can be printed. Empty ones stay empty.
+fn redact<S: serde::Serializer>(secret: &Secret<String>, serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.serialize_str(if secret.expose_secret().is_empty() { "" } else { "[REDACTED]" })
+}
+
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
 pub struct Settings {
 	pub database: DatabaseSettings,
 	pub application: ApplicationSettings,
 	pub email_client: EmailClientSettings,
 }
 
-#[derive(serde::Deserialize,Clone)]
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
 pub struct ApplicationSettings {
 	pub port: u16,
 	pub host: String,
 	pub base_url: String,
+	pub idempotency_ttl_seconds: u64,
+	#[serde(serialize_with = "redact")]
+	pub hmac_secret: Secret<String>,
+	pub session_store: SessionStoreSettings,
+	pub confirmation_token_ttl_seconds: u64,
+	pub confirmation_resend_cooldown_seconds: u64,
+	pub email_templates_directory: String,
+	pub postmark_webhook: PostmarkWebhookSettings,
+	/// Apply pending database migrations before serving requests.
+	pub run_migrations: bool,
+	pub health: HealthSettings,
+}
+
+/// Tuning of the `/health/ready` readiness probe and of graceful shutdown.
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
+pub struct HealthSettings {
+	/// Also check that the email transports are reachable, rather than only reporting their circuits.
+	pub probe_email_provider: bool,
+	pub check_timeout_milliseconds: u64,
+	/// How long readiness requests reuse the outcome of the last email probe.
+	pub email_probe_cache_seconds: u64,
+	/// How long readiness fails before the server stops accepting connections on shutdown,
+	/// leaving load balancers time to take the instance out of rotation.
+	pub shutdown_drain_seconds: u64,
+}
+
+impl HealthSettings {
+	pub fn check_timeout(&self) -> std::time::Duration {
+		std::time::Duration::from_millis(self.check_timeout_milliseconds)
+	}
+
+	pub fn email_probe_cache_ttl(&self) -> std::time::Duration {
+		std::time::Duration::from_secs(self.email_probe_cache_seconds)
+	}
+
+	pub fn shutdown_drain_period(&self) -> std::time::Duration {
+		std::time::Duration::from_secs(self.shutdown_drain_seconds)
+	}
+}
+
+/// Basic-auth credentials Postmark must present when calling our webhooks.
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
+pub struct PostmarkWebhookSettings {
+	pub username: String,
+	#[serde(serialize_with = "redact")]
+	pub password: Secret<String>,
+}
+
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreSettings {
+	Postgres,
+	Memory,
 }
 
-#[derive(serde::Deserialize,Clone)]
+impl ApplicationSettings {
+	pub fn idempotency_ttl(&self) -> std::time::Duration {
+		std::time::Duration::from_secs(self.idempotency_ttl_seconds)
+	}
+
+	pub fn confirmation_token_ttl(&self) -> std::time::Duration {
+		std::time::Duration::from_secs(self.confirmation_token_ttl_seconds)
+	}
+
+	pub fn confirmation_resend_cooldown(&self) -> std::time::Duration {
+		std::time::Duration::from_secs(self.confirmation_resend_cooldown_seconds)
+	}
+}
+
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
 pub struct DatabaseSettings {
 	pub username: String,
+	#[serde(serialize_with = "redact")]
 	pub password: Secret<String>,
 	pub port: u16,
 	pub host: String,
@@ -49,15 +137,128 @@ impl DatabaseSettings {
 	}
 }
 
-#[derive(serde::Deserialize,Clone)]
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
 pub struct EmailClientSettings {
+	pub transport: EmailTransportSettings,
 	pub base_url: String,
 	pub sender_email: String,
+	#[serde(serialize_with = "redact")]
 	pub authorization_token: Secret<String>,
 	pub timeout_milliseconds: u64,
+	pub smtp: SmtpSettings,
+	pub outbox_directory: String,
+	pub retry: RetrySettings,
+	pub rate_limit: RateLimitSettings,
+	pub circuit_breaker: CircuitBreakerSettings,
+	/// Takes over while the primary transport is failing.
+	pub failover_transport: Option<EmailTransportSettings>,
+}
+
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
+pub struct CircuitBreakerSettings {
+	pub failure_threshold: u32,
+	pub open_duration_milliseconds: u64,
+}
+
+impl CircuitBreakerSettings {
+	pub fn policy(&self) -> CircuitBreakerPolicy {
+		CircuitBreakerPolicy {
+			failure_threshold: self.failure_threshold,
+			open_duration: std::time::Duration::from_millis(self.open_duration_milliseconds),
+		}
+	}
+}
+
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
+pub struct RateLimitSettings {
+	pub messages_per_second: f64,
+	pub max_in_flight_requests: usize,
+}
+
+impl RateLimitSettings {
+	pub fn limiter(&self) -> RateLimiter {
+		RateLimiter::new(self.messages_per_second, self.max_in_flight_requests)
+	}
+}
+
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
+pub struct RetrySettings {
+	pub max_attempts: u32,
+	pub base_delay_milliseconds: u64,
+	pub max_delay_milliseconds: u64,
+	pub jitter: f64,
+}
+
+impl RetrySettings {
+	pub fn policy(&self) -> RetryPolicy {
+		RetryPolicy {
+			max_attempts: self.max_attempts,
+			base_delay: std::time::Duration::from_millis(self.base_delay_milliseconds),
+			max_delay: std::time::Duration::from_millis(self.max_delay_milliseconds),
+			jitter: self.jitter,
+		}
+	}
+}
+
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportSettings {
+	Postmark,
+	Smtp,
+	File,
+}
+
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
+pub struct SmtpSettings {
+	pub host: String,
+	pub port: u16,
+	pub username: String,
+	#[serde(serialize_with = "redact")]
+	pub password: Secret<String>,
+	pub tls: SmtpTlsSettings,
+}
+
+#[derive(serde::Deserialize,serde::Serialize,Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTlsSettings {
+	StartTls,
+	Implicit,
+	None,
 }
 
 impl EmailClientSettings {
+	pub fn client(self) -> Result<EmailClient, anyhow::Error> {
+		let sender_email = self
+			.sender()
+			.map_err(anyhow::Error::msg)
+			.context("Invalid sender email address.")?;
+		let mut email_client = EmailClient::new(sender_email, self.build_transport(&self.transport)?)
+			.with_retry_policy(self.retry.policy())
+			.with_rate_limiter(self.rate_limit.limiter())
+			.with_circuit_breaker(self.circuit_breaker.policy());
+		if let Some(failover_transport) = &self.failover_transport {
+			email_client = email_client.with_failover(self.build_transport(failover_transport)?);
+		}
+		Ok(email_client)
+	}
+	fn build_transport(&self, transport: &EmailTransportSettings) -> Result<Box<dyn EmailTransport>, anyhow::Error> {
+		Ok(match transport {
+			EmailTransportSettings::Postmark => Box::new(PostmarkTransport::new(
+				self.base_url.clone(),
+				self.authorization_token.clone(),
+				self.timeout(),
+			)),
+			EmailTransportSettings::Smtp => Box::new(
+				SmtpTransport::new(&self.smtp, self.timeout()).context("Invalid SMTP relay settings.")?,
+			),
+			EmailTransportSettings::File => Box::new(FileTransport::new(self.outbox_directory.clone())),
+		})
+	}
+	fn uses(&self, transport: &EmailTransportSettings) -> bool {
+		std::iter::once(&self.transport)
+			.chain(&self.failover_transport)
+			.any(|used| std::mem::discriminant(used) == std::mem::discriminant(transport))
+	}
 	pub fn sender(&self) -> Result<SubscriberEmail, String> {
 		SubscriberEmail::parse(self.sender_email.clone())
 	}
@@ -66,47 +267,367 @@ impl EmailClientSettings {
 	}
 }
 
-pub fn get_configuration() -> Result<Settings, config::ConfigError> {
-	let run_mode = std::env::var("APP_ENVIRONMENT").unwrap_or("development".into());
-	let base_path = std::env::current_dir().expect("Failed to determine the current directory");
-	let configuration_directory = base_path.join("configuration"); // "./configuration"
+/// Loads the settings of the environment named by `APP_ENVIRONMENT`, `development` if unset,
+/// from the directory named by `APP_CONFIGURATION_DIRECTORY`, `./configuration` if unset.
+pub fn get_configuration() -> Result<Settings, ConfigurationError> {
+	get_configuration_from(&configuration_directory()?, Environment::from_env()?)
+}
 
-	let base_config_file = configuration_directory.join("base"); // "./configuration/base"
-	let base_config_file = base_config_file.to_str().expect("Invalid base config file path");
-	let environment_config_file = configuration_directory.join(run_mode.to_lowercase()); // "./configuration/[development/production]"
-	let environment_config_file = environment_config_file.to_str().expect("Invalid environment config file path");
+/// Layers `base.yaml`, the environment's own file, then the `APP_` environment variables.
+pub fn get_configuration_from(directory: &Path, environment: Environment) -> Result<Settings, ConfigurationError> {
+	let (secret_files, env_vars): (config::Map<_, _>, config::Map<_, _>) = std::env::vars()
+		.filter(|(name, _)| name.starts_with(ENV_PREFIX))
+		.partition(|(name, _)| name.ends_with(ENV_FILE_SUFFIX));
+	let mut builder = Config::builder()
+		.add_source(config::File::from(directory.join("base")).required(true))
+		.add_source(config::File::from(directory.join(environment.as_str())).required(true))
+		.add_source(
+			config::Environment::with_prefix(ENV_PREFIX)
+				.prefix_separator("_")
+				.separator(ENV_SEPARATOR)
+				.try_parsing(true)
+				.source(Some(env_vars)),
+		);
+	for (key, value) in read_secret_files(&secret_files)? {
+		builder = builder.set_override(key, value)?;
+	}
 
-	let settings = Config::builder()
-		.add_source(config::File::with_name(base_config_file).required(true))
-		.add_source(config::File::with_name(environment_config_file).required(true))
-		.add_source(config::Environment::with_prefix("APP").try_parsing(true).separator("_"))
-		.build()?;
+	let settings: Settings = builder.build()?.try_deserialize()?;
+	settings.validate()?;
+	Ok(settings)
+}
+
+pub fn configuration_directory() -> Result<PathBuf, ConfigurationError> {
+	match std::env::var_os("APP_CONFIGURATION_DIRECTORY") {
+		Some(directory) => Ok(directory.into()),
+		None => Ok(std::env::current_dir()
+			.map_err(ConfigurationError::WorkingDirectory)?
+			.join("configuration")),
+	}
+}
 
-	settings.try_deserialize()
+/// Reads the settings given as `APP_<PATH>_FILE=<file>`, such as Docker or Kubernetes secret
+/// mounts, returning them as `(path, value)` overrides: `APP_DATABASE__PASSWORD_FILE` sets
+/// `database.password`. A single trailing newline is stripped from the file.
+fn read_secret_files(env_vars: &config::Map<String, String>) -> Result<Vec<(String, String)>, ConfigurationError> {
+	env_vars
+		.iter()
+		.filter_map(|(name, path)| {
+			let key = name
+				.strip_prefix(ENV_PREFIX)?
+				.strip_prefix('_')?
+				.strip_suffix(ENV_FILE_SUFFIX)?
+				.to_lowercase()
+				.replace(ENV_SEPARATOR, ".");
+			Some((name, key, path))
+		})
+		.map(|(name, key, path)| {
+			let value = std::fs::read_to_string(path).map_err(|source| ConfigurationError::SecretFile {
+				variable: name.clone(),
+				path: path.clone(),
+				source,
+			})?;
+			let value = value.strip_suffix('\n').unwrap_or(&value);
+			let value = value.strip_suffix('\r').unwrap_or(value);
+			Ok((key, value.to_owned()))
+		})
+		.collect()
 }
 
+#[derive(thiserror::Error)]
+pub enum ConfigurationError {
+	#[error(transparent)]
+	UnknownEnvironment(#[from] UnknownEnvironment),
+	#[error("Failed to determine the current directory to look for the configuration in.")]
+	WorkingDirectory(#[source] std::io::Error),
+	#[error("Failed to load the configuration.")]
+	Load(#[from] config::ConfigError),
+	#[error("Failed to read {path}, referenced by {variable}.")]
+	SecretFile {
+		variable: String,
+		path: String,
+		#[source]
+		source: std::io::Error,
+	},
+	#[error("The configuration is invalid:{}", .0.iter().map(|setting| format!("\n  - {}", setting)).collect::<String>())]
+	Invalid(Vec<InvalidSetting>),
+}
+
+impl std::fmt::Debug for ConfigurationError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		error_chain_fmt(self, f)
+	}
+}
+
+/// A setting that deserialized fine but holds a value we cannot run with.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidSetting {
+	pub key: &'static str,
+	pub reason: String,
+}
+
+impl std::fmt::Display for InvalidSetting {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}: {}", self.key, self.reason)
+	}
+}
+
+impl Settings {
+	/// Checks what deserialization cannot, reporting every invalid setting at once.
+	pub fn validate(&self) -> Result<(), ConfigurationError> {
+		let mut invalid = Vec::new();
+		let mut check = |key, reason: Option<String>| {
+			if let Some(reason) = reason {
+				invalid.push(InvalidSetting { key, reason });
+			}
+		};
+		let application = &self.application;
+		check("application.base_url", invalid_http_url(&application.base_url));
+		check("application.hmac_secret", short_secret(&application.hmac_secret, MIN_HMAC_SECRET_BYTES));
+		check("application.idempotency_ttl_seconds", zero(application.idempotency_ttl_seconds));
+		check("application.confirmation_token_ttl_seconds", zero(application.confirmation_token_ttl_seconds));
+		check("application.postmark_webhook.password", missing_secret(&application.postmark_webhook.password));
+		check("application.health.check_timeout_milliseconds", zero(application.health.check_timeout_milliseconds));
+
+		let email_client = &self.email_client;
+		check("email_client.sender_email", email_client.sender().err());
+		check("email_client.timeout_milliseconds", zero(email_client.timeout_milliseconds));
+		if email_client.uses(&EmailTransportSettings::Postmark) {
+			check("email_client.base_url", invalid_http_url(&email_client.base_url));
+			check("email_client.authorization_token", missing_secret(&email_client.authorization_token));
+		}
+		if email_client.uses(&EmailTransportSettings::Smtp) && email_client.smtp.host.trim().is_empty() {
+			check("email_client.smtp.host", Some("must not be empty".into()));
+		}
+		check("email_client.retry.max_attempts", zero(email_client.retry.max_attempts));
+		if !(0.0..=1.0).contains(&email_client.retry.jitter) {
+			check("email_client.retry.jitter", Some("must be between 0 and 1".into()));
+		}
+		let messages_per_second = email_client.rate_limit.messages_per_second;
+		if !(messages_per_second.is_finite() && messages_per_second > 0.) {
+			check("email_client.rate_limit.messages_per_second", Some("must be greater than zero".into()));
+		}
+		check("email_client.rate_limit.max_in_flight_requests", zero(email_client.rate_limit.max_in_flight_requests));
+		check("email_client.circuit_breaker.failure_threshold", zero(email_client.circuit_breaker.failure_threshold));
+
+		if invalid.is_empty() {
+			Ok(())
+		} else {
+			Err(ConfigurationError::Invalid(invalid))
+		}
+	}
+}
+
+fn invalid_http_url(url: &str) -> Option<String> {
+	match reqwest::Url::parse(url) {
+		Ok(url) if matches!(url.scheme(), "http" | "https") => None,
+		Ok(url) => Some(format!("`{}` is not reachable over HTTP, its scheme must be http or https", url)),
+		Err(e) => Some(format!("`{}` is not a valid URL: {}", url, e)),
+	}
+}
+
+fn missing_secret(secret: &Secret<String>) -> Option<String> {
+	secret.expose_secret().is_empty().then(|| "must not be empty".into())
+}
+
+fn short_secret(secret: &Secret<String>, min_bytes: usize) -> Option<String> {
+	missing_secret(secret).or_else(|| {
+		(secret.expose_secret().len() < min_bytes).then(|| format!("must be at least {} bytes long", min_bytes))
+	})
+}
+
+fn zero<T: Default + PartialEq>(value: T) -> Option<String> {
+	(value == T::default()).then(|| "must be greater than zero".into())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Environment {
+	/// A developer's machine, sending emails to a local SMTP catcher.
+	Local,
 	Development,
+	/// The integration test suite.
+	Test,
+	Staging,
 	Production,
 }
 
 impl Environment {
+	pub const ALL: [Environment; 5] = [
+		Environment::Local,
+		Environment::Development,
+		Environment::Test,
+		Environment::Staging,
+		Environment::Production,
+	];
+
+	/// Reads `APP_ENVIRONMENT`, defaulting to `development`.
+	pub fn from_env() -> Result<Self, UnknownEnvironment> {
+		std::env::var("APP_ENVIRONMENT")
+			.unwrap_or_else(|_| Environment::Development.as_str().into())
+			.try_into()
+	}
+
 	pub fn as_str(&self) -> &'static str {
 		match self {
+			Environment::Local => "local",
 			Environment::Development => "development",
+			Environment::Test => "test",
+			Environment::Staging => "staging",
 			Environment::Production => "production",
 		}
 	}
 }
 
 impl TryFrom<String> for Environment {
-	type Error = String;
+	type Error = UnknownEnvironment;
 
 	fn try_from(s: String) -> Result<Self, Self::Error> {
-		match s.to_lowercase().as_str() {
-			"development" => Ok(Environment::Development),
-			"production" => Ok(Environment::Production),
-			_ => Err(format!("{} is not a valid environment", s)),
+		let name = s.to_lowercase();
+		Environment::ALL
+			.into_iter()
+			.find(|environment| environment.as_str() == name)
+			.ok_or(UnknownEnvironment(s))
+	}
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(
+	"`{0}` is not a supported environment. Use one of: {}.",
+	Environment::ALL.map(|environment| environment.as_str()).join(", ")
+)]
+pub struct UnknownEnvironment(String);
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+
+	use claim::assert_ok;
+	use secrecy::Secret;
+
+	use super::{
+		get_configuration_from, read_secret_files, ConfigurationError, EmailTransportSettings, Environment, Settings,
+	};
+
+	fn settings() -> Settings {
+		get_configuration_from(Path::new("configuration"), Environment::Development)
+			.expect("Failed to read the development configuration.")
+	}
+
+	fn invalid_keys(settings: &Settings) -> Vec<&'static str> {
+		match settings.validate() {
+			Err(ConfigurationError::Invalid(invalid)) => invalid.into_iter().map(|setting| setting.key).collect(),
+			outcome => panic!("Expected the settings to be invalid, got {:?}", outcome),
 		}
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn the_profiles_that_ship_their_secrets_load() {
+		for environment in [Environment::Local, Environment::Development, Environment::Test] {
+			assert_ok!(get_configuration_from(Path::new("configuration"), environment));
+		}
+	}
+
+	#[test]
+	fn environments_are_parsed_case_insensitively() {
+		assert_eq!(Environment::try_from("Staging".to_owned()).unwrap(), Environment::Staging);
+	}
+
+	#[test]
+	fn unknown_environments_list_the_supported_ones() {
+		let error = Environment::try_from("prod".to_owned()).unwrap_err();
+
+		assert_eq!(
+			error.to_string(),
+			"`prod` is not a supported environment. Use one of: local, development, test, staging, production."
+		);
+	}
+
+	#[test]
+	fn every_invalid_setting_is_reported_at_once() {
+		let mut settings = settings();
+		settings.email_client.sender_email = "definitely-not-an-email".into();
+		settings.email_client.timeout_milliseconds = 0;
+		settings.application.base_url = "127.0.0.1:8000".into();
+
+		assert_eq!(
+			invalid_keys(&settings),
+			["application.base_url", "email_client.sender_email", "email_client.timeout_milliseconds"]
+		);
+	}
+
+	#[test]
+	fn the_hmac_secret_must_be_long_enough_for_a_cookie_key() {
+		let mut settings = settings();
+		settings.application.hmac_secret = Secret::new("short".into());
+
+		assert_eq!(invalid_keys(&settings), ["application.hmac_secret"]);
+	}
+
+	#[test]
+	fn ttls_must_not_be_zero() {
+		let mut settings = settings();
+		settings.application.idempotency_ttl_seconds = 0;
+		settings.application.confirmation_token_ttl_seconds = 0;
+
+		assert_eq!(
+			invalid_keys(&settings),
+			["application.idempotency_ttl_seconds", "application.confirmation_token_ttl_seconds"]
+		);
+	}
+
+	#[test]
+	fn base_urls_must_be_http_urls() {
+		let mut settings = settings();
+		settings.application.base_url = "ftp://127.0.0.1".into();
+
+		assert_eq!(invalid_keys(&settings), ["application.base_url"]);
+	}
+
+	#[test]
+	fn postmark_settings_are_only_checked_when_postmark_is_used() {
+		let mut settings = settings();
+		settings.email_client.transport = EmailTransportSettings::File;
+		settings.email_client.base_url = "localhost".into();
+		settings.email_client.authorization_token = Secret::new(String::new());
+		assert_ok!(settings.validate());
+
+		settings.email_client.failover_transport = Some(EmailTransportSettings::Postmark);
+		assert_eq!(invalid_keys(&settings), ["email_client.base_url", "email_client.authorization_token"]);
+	}
+
+	#[test]
+	fn secrets_are_redacted_when_serialized() {
+		let settings = serde_json::to_value(settings()).unwrap();
+
+		assert_eq!(settings["database"]["password"], "[REDACTED]");
+		assert_eq!(settings["email_client"]["authorization_token"], "[REDACTED]");
+		assert_eq!(settings["application"]["hmac_secret"], "[REDACTED]");
+		assert_eq!(settings["email_client"]["smtp"]["password"], "");
+		assert_eq!(settings["database"]["username"], "postgres");
+	}
+
+	#[test]
+	fn secret_files_override_the_setting_they_name() {
+		let path = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+		std::fs::write(&path, "s3cr3t\n").unwrap();
+		let env_vars = [("APP_EMAIL_CLIENT__AUTHORIZATION_TOKEN_FILE".to_owned(), path.display().to_string())]
+			.into_iter()
+			.collect();
+
+		let overrides = read_secret_files(&env_vars).unwrap();
+
+		assert_eq!(overrides, [("email_client.authorization_token".to_owned(), "s3cr3t".to_owned())]);
+		std::fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn a_missing_secret_file_is_an_error() {
+		let env_vars = [("APP_DATABASE__PASSWORD_FILE".to_owned(), "/definitely/not/a/secret".to_owned())]
+			.into_iter()
+			.collect();
+
+		let outcome = read_secret_files(&env_vars);
+
+		assert!(matches!(outcome, Err(ConfigurationError::SecretFile { .. })));
+	}
+}
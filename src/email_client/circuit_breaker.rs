@@ -0,0 +1,236 @@
+use std::{
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+/// When a `CircuitBreaker` stops sending requests to a failing transport, and for how long.
+#[derive(Clone, Debug)]
+pub struct CircuitBreakerPolicy {
+	/// Consecutive transient failures after which the circuit opens.
+	pub failure_threshold: u32,
+	/// How long the circuit stays open before a single probe request is let through.
+	pub open_duration: Duration,
+}
+
+impl Default for CircuitBreakerPolicy {
+	/// Never opens.
+	fn default() -> Self {
+		Self {
+			failure_threshold: u32::MAX,
+			open_duration: Duration::ZERO,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+	Closed,
+	Open,
+	HalfOpen,
+}
+
+/// Short-circuits calls to a transport after too many consecutive failures, so callers
+/// fail fast (or fail over) instead of waiting for every request to time out.
+pub struct CircuitBreaker {
+	policy: CircuitBreakerPolicy,
+	state: Mutex<State>,
+}
+
+enum State {
+	Closed { consecutive_failures: u32 },
+	Open { until: Instant },
+	/// A probe request is in flight; everything else is rejected until it completes.
+	HalfOpen,
+}
+
+impl CircuitBreaker {
+	pub fn new(policy: CircuitBreakerPolicy) -> Self {
+		Self {
+			policy,
+			state: Mutex::new(State::Closed { consecutive_failures: 0 }),
+		}
+	}
+
+	pub fn state(&self) -> CircuitState {
+		match *self.lock() {
+			State::Closed { .. } => CircuitState::Closed,
+			State::Open { .. } => CircuitState::Open,
+			State::HalfOpen => CircuitState::HalfOpen,
+		}
+	}
+
+	/// Lets a request through, or returns how long until the circuit half-opens.
+	///
+	/// Letting a request through an expired open circuit turns it into the probe.
+	pub fn try_acquire(&self) -> Result<CircuitPermit<'_>, Duration> {
+		let mut state = self.lock();
+		match *state {
+			State::Closed { .. } => {}
+			State::Open { until } => {
+				let now = Instant::now();
+				if now < until {
+					return Err(until - now);
+				}
+				*state = State::HalfOpen;
+			}
+			State::HalfOpen => return Err(self.policy.open_duration),
+		}
+		Ok(CircuitPermit {
+			circuit_breaker: self,
+			resolved: false,
+		})
+	}
+
+	fn record_success(&self) {
+		*self.lock() = State::Closed { consecutive_failures: 0 };
+	}
+
+	fn record_failure(&self) {
+		let mut state = self.lock();
+		let consecutive_failures = match *state {
+			State::Closed { consecutive_failures } => consecutive_failures.saturating_add(1),
+			// The probe failed, the transport is still down.
+			State::HalfOpen => self.policy.failure_threshold,
+			State::Open { .. } => return,
+		};
+		*state = if consecutive_failures >= self.policy.failure_threshold {
+			tracing::warn!(consecutive_failures, "Opening the circuit of an email transport.");
+			State::Open {
+				until: Instant::now() + self.policy.open_duration,
+			}
+		} else {
+			State::Closed { consecutive_failures }
+		};
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+		self.state.lock().expect("The circuit breaker lock is poisoned.")
+	}
+}
+
+/// A request let through by `CircuitBreaker::try_acquire`, whose outcome must be recorded.
+///
+/// Dropping it unresolved, as when the request is cancelled, counts as a failure:
+/// otherwise a cancelled probe would leave the circuit half-open for good.
+#[must_use]
+pub struct CircuitPermit<'a> {
+	circuit_breaker: &'a CircuitBreaker,
+	resolved: bool,
+}
+
+impl std::fmt::Debug for CircuitPermit<'_> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("CircuitPermit").field("resolved", &self.resolved).finish_non_exhaustive()
+	}
+}
+
+impl CircuitPermit<'_> {
+	pub fn succeeded(mut self) {
+		self.resolved = true;
+		self.circuit_breaker.record_success();
+	}
+
+	pub fn failed(mut self) {
+		self.resolved = true;
+		self.circuit_breaker.record_failure();
+	}
+}
+
+impl Drop for CircuitPermit<'_> {
+	fn drop(&mut self) {
+		if !self.resolved {
+			self.circuit_breaker.record_failure();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use claim::{assert_err, assert_ok};
+
+	use super::{CircuitBreaker, CircuitBreakerPolicy, CircuitState};
+
+	fn circuit_breaker(open_duration: Duration) -> CircuitBreaker {
+		CircuitBreaker::new(CircuitBreakerPolicy {
+			failure_threshold: 3,
+			open_duration,
+		})
+	}
+
+	#[test]
+	fn the_circuit_opens_after_consecutive_failures() {
+		let breaker = circuit_breaker(Duration::from_secs(60));
+
+		for _ in 0..3 {
+			breaker.try_acquire().unwrap().failed();
+		}
+
+		assert_eq!(breaker.state(), CircuitState::Open);
+		assert_err!(breaker.try_acquire());
+	}
+
+	#[test]
+	fn a_success_resets_the_failure_count() {
+		let breaker = circuit_breaker(Duration::from_secs(60));
+
+		breaker.record_failure();
+		breaker.record_failure();
+		breaker.record_success();
+		breaker.record_failure();
+
+		assert_eq!(breaker.state(), CircuitState::Closed);
+	}
+
+	#[test]
+	fn an_expired_open_circuit_lets_a_single_probe_through() {
+		let breaker = circuit_breaker(Duration::ZERO);
+		for _ in 0..3 {
+			breaker.record_failure();
+		}
+
+		let _probe = breaker.try_acquire().unwrap();
+		assert_eq!(breaker.state(), CircuitState::HalfOpen);
+		assert_err!(breaker.try_acquire());
+	}
+
+	#[test]
+	fn a_successful_probe_closes_the_circuit() {
+		let breaker = circuit_breaker(Duration::ZERO);
+		for _ in 0..3 {
+			breaker.record_failure();
+		}
+		let probe = breaker.try_acquire().unwrap();
+
+		probe.succeeded();
+
+		assert_eq!(breaker.state(), CircuitState::Closed);
+	}
+
+	#[test]
+	fn a_dropped_probe_reopens_the_circuit() {
+		let breaker = circuit_breaker(Duration::ZERO);
+		for _ in 0..3 {
+			breaker.record_failure();
+		}
+		let probe = breaker.try_acquire().unwrap();
+
+		drop(probe);
+
+		assert_eq!(breaker.state(), CircuitState::Open);
+		// Once the open duration has elapsed, the next request becomes the probe.
+		let _next_probe = assert_ok!(breaker.try_acquire());
+	}
+
+	#[test]
+	fn a_failed_probe_opens_the_circuit_again() {
+		let breaker = circuit_breaker(Duration::from_secs(60));
+		*breaker.lock() = super::State::HalfOpen;
+
+		breaker.record_failure();
+
+		assert_eq!(breaker.state(), CircuitState::Open);
+	}
+}
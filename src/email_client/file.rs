@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+
+use super::{to_mime_message, Email, EmailTransport, SendEmailError};
+
+/// Writes every email as an `.eml` file into a local outbox directory instead of sending it.
+pub struct FileTransport {
+	directory: PathBuf,
+	transport: AsyncFileTransport<Tokio1Executor>,
+}
+
+impl FileTransport {
+	pub fn new(directory: impl Into<PathBuf>) -> Self {
+		let directory = directory.into();
+		Self {
+			transport: AsyncFileTransport::new(&directory),
+			directory,
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileTransport {
+	fn name(&self) -> &'static str {
+		"file"
+	}
+
+	async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError> {
+		let message = to_mime_message(email)?;
+		tokio::fs::create_dir_all(&self.directory).await?;
+		self.transport.send(message).await?;
+		Ok(())
+	}
+
+	async fn probe(&self) -> Result<(), SendEmailError> {
+		tokio::fs::create_dir_all(&self.directory).await?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use claim::assert_ok;
+	use fake::{faker::internet::en::SafeEmail, Fake};
+	use uuid::Uuid;
+
+	use crate::{domain::SubscriberEmail, email_client::EmailClient};
+
+	use super::FileTransport;
+
+	#[tokio::test]
+	async fn send_email_writes_an_eml_file_to_the_outbox_directory() {
+		let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+		let sender = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+		let recipient = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+		let email_client = EmailClient::new(sender, FileTransport::new(&directory));
+
+		let outcome = email_client
+			.send_email(&recipient, "Welcome!", "<p>Hi!</p>", "Hi!")
+			.await;
+
+		assert_ok!(outcome);
+		let files: Vec<_> = std::fs::read_dir(&directory).unwrap().collect();
+		assert_eq!(files.len(), 1);
+		let path = files[0].as_ref().unwrap().path();
+		assert_eq!(path.extension().unwrap(), "eml");
+		let contents = std::fs::read_to_string(&path).unwrap();
+		assert!(contents.contains("Subject: Welcome!"));
+		assert!(contents.contains(recipient.as_ref()));
+		std::fs::remove_dir_all(&directory).unwrap();
+	}
+}
@@ -0,0 +1,730 @@
+mod circuit_breaker;
+mod file;
+mod outgoing;
+mod postmark;
+mod rate_limit;
+mod retry;
+mod smtp;
+
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use lettre::message::{
+	header::{ContentType, HeaderName, HeaderValue},
+	Mailbox, MultiPart, SinglePart,
+};
+use tracing::Instrument;
+
+use crate::domain::SubscriberEmail;
+
+pub use circuit_breaker::{CircuitBreaker, CircuitBreakerPolicy, CircuitPermit, CircuitState};
+pub use file::FileTransport;
+pub use outgoing::{Attachment, OutgoingEmail};
+pub use postmark::PostmarkTransport;
+pub use rate_limit::{RateLimiter, SendPermit};
+pub use retry::RetryPolicy;
+pub use smtp::SmtpTransport;
+
+/// Postmark accepts at most 500 messages per `/email/batch` call.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+/// A fully addressed message, ready to be handed over to an `EmailTransport`.
+pub struct Email<'a> {
+	pub from: &'a SubscriberEmail,
+	pub message: &'a OutgoingEmail,
+}
+
+/// What happened to a single message of a batch.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+	Delivered,
+	RetryLater,
+	FailedPermanently,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SendEmailError {
+	#[error("Failed to send the email through the Postmark API.")]
+	Postmark(#[from] reqwest::Error),
+	#[error("The email provider rejected the request with status {status}.")]
+	Rejected {
+		status: u16,
+		retry_after: Option<std::time::Duration>,
+	},
+	#[error("Failed to send the email through the SMTP relay.")]
+	Smtp(#[from] lettre::transport::smtp::Error),
+	#[error("Failed to write the email to the outbox directory.")]
+	File(#[from] lettre::transport::file::Error),
+	#[error("Failed to create the outbox directory.")]
+	Outbox(#[from] std::io::Error),
+	#[error("Failed to build the email message.")]
+	InvalidMessage(#[source] anyhow::Error),
+	#[error("The email provider sent a response we could not make sense of.")]
+	UnexpectedResponse(#[source] anyhow::Error),
+	#[error("Every email transport is failing, their circuits are open.")]
+	CircuitOpen { retry_after: Duration },
+}
+
+impl SendEmailError {
+	/// Timeouts, dropped connections, throttling and provider-side failures are worth
+	/// another attempt; everything else will fail the same way again.
+	pub fn is_transient(&self) -> bool {
+		match self {
+			SendEmailError::Postmark(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+			SendEmailError::Rejected { status, .. } => *status == 429 || *status >= 500,
+			SendEmailError::CircuitOpen { .. } => true,
+			SendEmailError::Smtp(e) => {
+				e.is_transient() || e.is_timeout() || !(e.is_permanent() || e.is_client() || e.is_response())
+			}
+			SendEmailError::File(_)
+			| SendEmailError::Outbox(_)
+			| SendEmailError::InvalidMessage(_)
+			| SendEmailError::UnexpectedResponse(_) => false,
+		}
+	}
+
+	/// Whether the provider may have accepted the request despite the error, as when it times
+	/// out after the request was sent: sending it again risks a duplicate.
+	pub fn may_have_been_delivered(&self) -> bool {
+		if self.was_accepted() {
+			return true;
+		}
+		match self {
+			SendEmailError::Postmark(e) => !e.is_connect(),
+			SendEmailError::Smtp(e) => e.is_timeout(),
+			_ => false,
+		}
+	}
+
+	/// Whether the provider answered with a success we could not make sense of, such as a
+	/// body that fails to decode: it took the request, only the per-message outcomes are lost.
+	pub fn was_accepted(&self) -> bool {
+		match self {
+			SendEmailError::Postmark(e) => e.is_decode() || e.is_body(),
+			SendEmailError::UnexpectedResponse(_) => true,
+			_ => false,
+		}
+	}
+
+	pub fn retry_after(&self) -> Option<std::time::Duration> {
+		match self {
+			SendEmailError::Rejected { retry_after, .. } => *retry_after,
+			SendEmailError::CircuitOpen { retry_after } => Some(*retry_after),
+			_ => None,
+		}
+	}
+}
+
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+	/// Identifies the transport in logs and health checks.
+	fn name(&self) -> &'static str;
+
+	async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError>;
+
+	/// Checks that the transport could take a message right now, without sending one.
+	async fn probe(&self) -> Result<(), SendEmailError> {
+		Ok(())
+	}
+
+	/// Whether `send_batch` submits the whole batch in a single request.
+	fn supports_batching(&self) -> bool {
+		false
+	}
+
+	/// Returns one outcome per email, in the order they were passed in.
+	async fn send_batch(&self, emails: &[Email<'_>]) -> Result<Vec<DeliveryOutcome>, SendEmailError> {
+		let mut outcomes = Vec::with_capacity(emails.len());
+		for email in emails {
+			outcomes.push(match self.send(email).await {
+				Ok(()) => DeliveryOutcome::Delivered,
+				Err(e) if e.is_transient() => DeliveryOutcome::RetryLater,
+				Err(_) => DeliveryOutcome::FailedPermanently,
+			});
+		}
+		Ok(outcomes)
+	}
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for Box<dyn EmailTransport> {
+	fn name(&self) -> &'static str {
+		self.as_ref().name()
+	}
+
+	async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError> {
+		self.as_ref().send(email).await
+	}
+
+	async fn probe(&self) -> Result<(), SendEmailError> {
+		self.as_ref().probe().await
+	}
+
+	fn supports_batching(&self) -> bool {
+		self.as_ref().supports_batching()
+	}
+
+	async fn send_batch(&self, emails: &[Email<'_>]) -> Result<Vec<DeliveryOutcome>, SendEmailError> {
+		self.as_ref().send_batch(emails).await
+	}
+}
+
+type SendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, SendEmailError>> + Send + 'a>>;
+
+/// A transport together with the circuit breaker guarding it.
+#[derive(Clone)]
+struct Provider {
+	transport: Arc<dyn EmailTransport>,
+	circuit_breaker: Arc<CircuitBreaker>,
+}
+
+impl Provider {
+	fn health(&self, role: &'static str) -> TransportHealth {
+		TransportHealth {
+			name: self.transport.name(),
+			role,
+			circuit: self.circuit_breaker.state(),
+		}
+	}
+}
+
+/// Whether a request may be sent again, in-process, after a transient failure.
+#[derive(Clone, Copy)]
+enum Resend {
+	Allowed,
+	/// For batches, where a duplicate is one per recipient: leave those failures to the caller.
+	UnlessPossiblyDelivered,
+}
+
+impl Resend {
+	fn forbids_after(self, error: &SendEmailError) -> bool {
+		matches!(self, Resend::UnlessPossiblyDelivered) && error.may_have_been_delivered()
+	}
+}
+
+/// The state of one of the transports of an `EmailClient`, as reported by health checks.
+#[derive(Debug, serde::Serialize)]
+pub struct TransportHealth {
+	pub name: &'static str,
+	pub role: &'static str,
+	pub circuit: CircuitState,
+}
+
+/// The outcome of probing one of the transports of an `EmailClient`.
+pub struct TransportProbe {
+	pub health: TransportHealth,
+	pub latency: Duration,
+	pub outcome: Result<(), SendEmailError>,
+}
+
+/// Cloning is cheap: clones share the transports, their circuit breakers and the rate limiter.
+#[derive(Clone)]
+pub struct EmailClient {
+	sender: SubscriberEmail,
+	primary: Provider,
+	failover: Option<Provider>,
+	retry_policy: RetryPolicy,
+	circuit_breaker_policy: CircuitBreakerPolicy,
+	rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+impl EmailClient {
+	pub fn new(sender: SubscriberEmail, transport: impl EmailTransport + 'static) -> Self {
+		let circuit_breaker_policy = CircuitBreakerPolicy::default();
+		Self {
+			sender,
+			primary: Provider {
+				transport: Arc::new(transport),
+				circuit_breaker: Arc::new(CircuitBreaker::new(circuit_breaker_policy.clone())),
+			},
+			failover: None,
+			retry_policy: RetryPolicy::default(),
+			circuit_breaker_policy,
+			rate_limiter: None,
+		}
+	}
+
+	pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+		self.retry_policy = retry_policy;
+		self
+	}
+
+	pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+		self.rate_limiter = Some(Arc::new(rate_limiter));
+		self
+	}
+
+	pub fn with_circuit_breaker(mut self, circuit_breaker_policy: CircuitBreakerPolicy) -> Self {
+		for provider in std::iter::once(&mut self.primary).chain(&mut self.failover) {
+			provider.circuit_breaker = Arc::new(CircuitBreaker::new(circuit_breaker_policy.clone()));
+		}
+		self.circuit_breaker_policy = circuit_breaker_policy;
+		self
+	}
+
+	/// Sends through `transport` whenever the primary one fails or its circuit is open.
+	pub fn with_failover(mut self, transport: impl EmailTransport + 'static) -> Self {
+		self.failover = Some(Provider {
+			transport: Arc::new(transport),
+			circuit_breaker: Arc::new(CircuitBreaker::new(self.circuit_breaker_policy.clone())),
+		});
+		self
+	}
+
+	pub fn transport_health(&self) -> Vec<TransportHealth> {
+		self.providers()
+			.map(|(role, provider)| provider.health(role))
+			.collect()
+	}
+
+	/// Probes every transport, bypassing the rate limiter and leaving the circuit breakers alone.
+	pub async fn probe_transports(&self) -> Vec<TransportProbe> {
+		let mut probes = Vec::new();
+		for (role, provider) in self.providers() {
+			let start = std::time::Instant::now();
+			let outcome = provider.transport.probe().await;
+			probes.push(TransportProbe {
+				health: provider.health(role),
+				latency: start.elapsed(),
+				outcome,
+			});
+		}
+		probes
+	}
+
+	fn providers(&self) -> impl Iterator<Item = (&'static str, &Provider)> {
+		std::iter::once(("primary", &self.primary)).chain(self.failover.iter().map(|provider| ("failover", provider)))
+	}
+
+	pub async fn send_email(
+		&self,
+		recipient: &SubscriberEmail,
+		subject: &str,
+		html_content: &str,
+		text_content: &str,
+	) -> Result<(), SendEmailError> {
+		self.send(&OutgoingEmail::new(recipient.clone(), subject, html_content, text_content))
+			.await
+	}
+
+	pub async fn send(&self, message: &OutgoingEmail) -> Result<(), SendEmailError> {
+		let email = Email {
+			from: &self.sender,
+			message,
+		};
+		self.with_retries(1, Resend::Allowed, |transport| transport.send(&email))
+			.await
+	}
+
+	pub fn supports_batching(&self) -> bool {
+		self.primary.transport.supports_batching()
+	}
+
+	/// The most messages `send_batch` submits in a single request.
+	pub fn max_batch_size(&self) -> usize {
+		let max_batch_size = if self.supports_batching() { MAX_BATCH_SIZE } else { 1 };
+		match &self.rate_limiter {
+			Some(rate_limiter) => max_batch_size.min(rate_limiter.burst()),
+			None => max_batch_size,
+		}
+	}
+
+	/// Returns how many messages can be handed to `send_batch` without being throttled,
+	/// or how long to wait until the rate limiter lets at least one through.
+	pub fn send_capacity(&self) -> Result<usize, Duration> {
+		match &self.rate_limiter {
+			Some(rate_limiter) => Ok(rate_limiter.capacity()?.min(self.max_batch_size())),
+			None => Ok(self.max_batch_size()),
+		}
+	}
+
+	/// Sends `emails` in chunks of at most `max_batch_size()` messages.
+	///
+	/// Returns one outcome per email, in order. An `Err` means the outcome of every
+	/// message in the failed chunk and the ones after it is unknown.
+	///
+	/// Delivery is at least once: a chunk that fails in a way the provider may have accepted it
+	/// anyway, such as a timeout, is neither retried nor sent through the failover transport,
+	/// but the caller is expected to try again later, which may deliver it twice. Unless the
+	/// error `was_accepted()`: then the chunk went out and must not be sent again.
+	pub async fn send_batch(&self, messages: &[OutgoingEmail]) -> Result<Vec<DeliveryOutcome>, SendEmailError> {
+		let emails: Vec<_> = messages
+			.iter()
+			.map(|message| Email {
+				from: &self.sender,
+				message,
+			})
+			.collect();
+		if !self.supports_batching() {
+			let mut outcomes = Vec::with_capacity(emails.len());
+			for email in &emails {
+				outcomes.push(match self.with_retries(1, Resend::Allowed, |transport| transport.send(email)).await {
+					Ok(()) => DeliveryOutcome::Delivered,
+					Err(e) if e.is_transient() => DeliveryOutcome::RetryLater,
+					Err(_) => DeliveryOutcome::FailedPermanently,
+				});
+			}
+			return Ok(outcomes);
+		}
+		let mut outcomes = Vec::with_capacity(emails.len());
+		for chunk in emails.chunks(self.max_batch_size()) {
+			outcomes.extend(
+				self.with_retries(chunk.len(), Resend::UnlessPossiblyDelivered, |transport| {
+					transport.send_batch(chunk)
+				})
+				.await?,
+			);
+		}
+		Ok(outcomes)
+	}
+
+	/// Waits for the rate limiter, if any, to let a request carrying `n_messages` through.
+	async fn acquire(&self, n_messages: usize) -> Option<SendPermit> {
+		match &self.rate_limiter {
+			Some(rate_limiter) => Some(rate_limiter.acquire(n_messages).await),
+			None => None,
+		}
+	}
+
+	async fn with_retries<'a, T, F>(
+		&'a self,
+		n_messages: usize,
+		resend: Resend,
+		operation: F,
+	) -> Result<T, SendEmailError>
+	where
+		F: Fn(&'a dyn EmailTransport) -> SendFuture<'a, T>,
+	{
+		let mut attempt = 1;
+		loop {
+			let span = tracing::info_span!("Email delivery attempt", attempt);
+			let result = {
+				let _permit = self.acquire(n_messages).await;
+				self.send_with_failover(&operation).instrument(span).await
+			};
+			let error = match result {
+				Ok(value) => return Ok(value),
+				Err(e) => e,
+			};
+			if resend.forbids_after(&error) {
+				return Err(error);
+			}
+			let Some(delay) = self.retry_policy.delay_before_retry(attempt, &error) else {
+				return Err(error);
+			};
+			tracing::warn!(
+				error.message = %error,
+				attempt,
+				retry_in_ms = delay.as_millis() as u64,
+				"Transient failure while sending an email. Retrying.",
+			);
+			tokio::time::sleep(delay).await;
+			attempt += 1;
+		}
+	}
+
+	/// Tries the primary transport, then the failover one, skipping those whose circuit is open.
+	///
+	/// Does not fail over after an error the provider may have accepted the request despite:
+	/// the failover transport would deliver it a second time.
+	async fn send_with_failover<'a, T, F>(&'a self, operation: &F) -> Result<T, SendEmailError>
+	where
+		F: Fn(&'a dyn EmailTransport) -> SendFuture<'a, T>,
+	{
+		let mut last_error = None;
+		for provider in std::iter::once(&self.primary).chain(&self.failover) {
+			let permit = match provider.circuit_breaker.try_acquire() {
+				Ok(permit) => permit,
+				Err(retry_after) => {
+					last_error = Some(SendEmailError::CircuitOpen { retry_after });
+					continue;
+				}
+			};
+			match operation(provider.transport.as_ref()).await {
+				Err(e) if e.is_transient() => {
+					permit.failed();
+					tracing::warn!(
+						error.message = %e,
+						transport = provider.transport.name(),
+						"Transient failure of an email transport.",
+					);
+					if e.may_have_been_delivered() {
+						return Err(e);
+					}
+					last_error = Some(e);
+				}
+				// A permanent rejection still proves the provider is up.
+				result => {
+					permit.succeeded();
+					return result;
+				}
+			}
+		}
+		Err(last_error.expect("An email client always has a primary transport."))
+	}
+}
+
+/// Builds the MIME message for the transports that speak RFC 5322.
+///
+/// Both bodies go into a `multipart/alternative` part, wrapped in `multipart/related`
+/// along with the inline images and in `multipart/mixed` along with the other attachments.
+/// Postmark's tag, metadata and message stream become their `X-PM-*` SMTP headers.
+fn to_mime_message(email: &Email<'_>) -> Result<lettre::Message, SendEmailError> {
+	let message = email.message;
+	let mut builder = lettre::Message::builder()
+		.from(mailbox(email.from)?)
+		.to(mailbox(&message.to)?)
+		.subject(&message.subject);
+	if let Some(reply_to) = &message.reply_to {
+		builder = builder.reply_to(mailbox(reply_to)?);
+	}
+	let mut body = MultiPart::alternative_plain_html(message.text_content.clone(), message.html_content.clone());
+	let (inline, attached): (Vec<_>, Vec<_>) = message.attachments.iter().partition(|a| a.is_inline());
+	if !inline.is_empty() {
+		let mut related = MultiPart::related().multipart(body);
+		for attachment in inline {
+			related = related.singlepart(mime_part(attachment)?);
+		}
+		body = related;
+	}
+	if !attached.is_empty() {
+		let mut mixed = MultiPart::mixed().multipart(body);
+		for attachment in attached {
+			mixed = mixed.singlepart(mime_part(attachment)?);
+		}
+		body = mixed;
+	}
+	let mut mime_message = builder.multipart(body).map_err(invalid_message)?;
+
+	let postmark_headers = message
+		.tag
+		.iter()
+		.map(|tag| ("X-PM-Tag".to_owned(), tag.clone()))
+		.chain(
+			message
+				.metadata
+				.iter()
+				.map(|(key, value)| (format!("X-PM-Metadata-{}", key), value.clone())),
+		)
+		.chain(
+			message
+				.message_stream
+				.iter()
+				.map(|stream| ("X-PM-Message-Stream".to_owned(), stream.clone())),
+		);
+	for (name, value) in message.headers.iter().cloned().chain(postmark_headers) {
+		let name = HeaderName::new_from_ascii(name).map_err(invalid_message)?;
+		mime_message.headers_mut().insert_raw(HeaderValue::new(name, value));
+	}
+	Ok(mime_message)
+}
+
+fn mailbox(email: &SubscriberEmail) -> Result<Mailbox, SendEmailError> {
+	email.as_ref().parse().map_err(invalid_message)
+}
+
+fn mime_part(attachment: &Attachment) -> Result<SinglePart, SendEmailError> {
+	let content_type = ContentType::parse(&attachment.content_type).map_err(invalid_message)?;
+	let mime_attachment = match &attachment.content_id {
+		Some(content_id) => lettre::message::Attachment::new_inline(content_id.clone()),
+		None => lettre::message::Attachment::new(attachment.name.clone()),
+	};
+	Ok(mime_attachment.body(attachment.content.clone(), content_type))
+}
+
+fn invalid_message(e: impl std::error::Error + Send + Sync + 'static) -> SendEmailError {
+	SendEmailError::InvalidMessage(anyhow::Error::new(e))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use claim::{assert_err, assert_ok};
+	use fake::{faker::internet::en::SafeEmail, Fake, Faker};
+	use secrecy::Secret;
+	use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
+
+	use crate::domain::SubscriberEmail;
+
+	use super::{
+		to_mime_message, Attachment, CircuitBreakerPolicy, CircuitState, Email, EmailClient, OutgoingEmail,
+		PostmarkTransport, SendEmailError,
+	};
+
+	fn email() -> SubscriberEmail {
+		SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+	}
+
+	fn transport(mock_server: &MockServer) -> PostmarkTransport {
+		PostmarkTransport::new(mock_server.uri(), Secret::new(Faker.fake()), Duration::from_millis(100))
+	}
+
+	async fn mock_server(status: u16, expected_calls: u64) -> MockServer {
+		let mock_server = MockServer::start().await;
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(status))
+			.expect(expected_calls)
+			.mount(&mock_server)
+			.await;
+		mock_server
+	}
+
+	fn circuit_breaker_policy() -> CircuitBreakerPolicy {
+		CircuitBreakerPolicy {
+			failure_threshold: 2,
+			open_duration: Duration::from_secs(60),
+		}
+	}
+
+	async fn send(email_client: &EmailClient) -> Result<(), SendEmailError> {
+		email_client.send_email(&email(), "Subject", "<p>Body</p>", "Body").await
+	}
+
+	#[test]
+	fn mime_messages_carry_both_bodies_and_the_custom_headers() {
+		let from = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+		let to = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+		let message = OutgoingEmail::new(
+			to,
+			"Newsletter title",
+			"<p>Newsletter body as HTML</p>",
+			"Newsletter body as plain text",
+		)
+		.header("List-Unsubscribe-Post", "List-Unsubscribe=One-Click");
+
+		let mime_message = to_mime_message(&Email { from: &from, message: &message });
+
+		assert_ok!(&mime_message);
+		let formatted = String::from_utf8(mime_message.unwrap().formatted()).unwrap();
+		assert!(formatted.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+		assert!(formatted.contains("Content-Type: text/plain"));
+		assert!(formatted.contains("Content-Type: text/html"));
+		assert!(!formatted.contains("multipart/mixed"));
+	}
+
+	#[test]
+	fn mime_messages_carry_attachments_inline_images_and_postmark_fields() {
+		let from = email();
+		let reply_to = email();
+		let message = OutgoingEmail::new(email(), "Issue #42", r#"<img src="cid:logo">"#, "Issue #42")
+			.reply_to(reply_to.clone())
+			.attachment(Attachment::new("issue-42.pdf", "application/pdf", b"%PDF-1.7".to_vec()))
+			.attachment(Attachment::inline("logo", "logo.png", "image/png", b"\x89PNG".to_vec()))
+			.tag("newsletter")
+			.metadata("newsletter_issue_id", "42")
+			.message_stream("broadcast");
+
+		let mime_message = to_mime_message(&Email { from: &from, message: &message }).unwrap();
+
+		let formatted = String::from_utf8(mime_message.formatted()).unwrap();
+		assert!(formatted.contains(&format!("Reply-To: {}", reply_to.as_ref())));
+		assert!(formatted.contains("Content-Type: multipart/mixed"));
+		assert!(formatted.contains("Content-Type: multipart/related"));
+		assert!(formatted.contains("Content-Disposition: attachment; filename=\"issue-42.pdf\""));
+		assert!(formatted.contains("Content-ID: <logo>"));
+		assert!(formatted.contains("X-PM-Tag: newsletter"));
+		assert!(formatted.contains("X-PM-Metadata-newsletter_issue_id: 42"));
+		assert!(formatted.contains("X-PM-Message-Stream: broadcast"));
+	}
+
+	#[test]
+	fn attachments_with_an_invalid_content_type_are_rejected() {
+		let from = email();
+		let message = OutgoingEmail::new(email(), "Subject", "<p>Body</p>", "Body")
+			.attachment(Attachment::new("issue.pdf", "not a content type", Vec::new()));
+
+		let error = to_mime_message(&Email { from: &from, message: &message }).unwrap_err();
+
+		assert!(matches!(error, SendEmailError::InvalidMessage(_)));
+	}
+
+	#[tokio::test]
+	async fn transient_failures_of_the_primary_transport_fail_over_to_the_secondary() {
+		let primary = mock_server(500, 1).await;
+		let failover = mock_server(200, 1).await;
+		let email_client = EmailClient::new(email(), transport(&primary)).with_failover(transport(&failover));
+
+		assert_ok!(send(&email_client).await);
+	}
+
+	#[tokio::test]
+	async fn a_timeout_of_the_primary_transport_does_not_fail_over() {
+		let primary = MockServer::start().await;
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+			.mount(&primary)
+			.await;
+		let failover = mock_server(200, 0).await;
+		let email_client = EmailClient::new(email(), transport(&primary)).with_failover(transport(&failover));
+
+		let error = send(&email_client).await.unwrap_err();
+
+		assert!(error.may_have_been_delivered());
+	}
+
+	#[tokio::test]
+	async fn permanent_rejections_do_not_fail_over() {
+		let primary = mock_server(422, 1).await;
+		let failover = mock_server(200, 0).await;
+		let email_client = EmailClient::new(email(), transport(&primary)).with_failover(transport(&failover));
+
+		assert_err!(send(&email_client).await);
+	}
+
+	#[tokio::test]
+	async fn an_open_circuit_skips_the_primary_transport() {
+		let primary = mock_server(500, 2).await;
+		let failover = mock_server(200, 3).await;
+		let email_client = EmailClient::new(email(), transport(&primary))
+			.with_circuit_breaker(circuit_breaker_policy())
+			.with_failover(transport(&failover));
+
+		for _ in 0..3 {
+			assert_ok!(send(&email_client).await);
+		}
+		let health = email_client.transport_health();
+		assert_eq!(health[0].circuit, CircuitState::Open);
+		assert_eq!(health[1].circuit, CircuitState::Closed);
+	}
+
+	#[tokio::test]
+	async fn an_open_circuit_without_failover_fails_fast() {
+		let primary = mock_server(500, 2).await;
+		let email_client = EmailClient::new(email(), transport(&primary)).with_circuit_breaker(circuit_breaker_policy());
+
+		for _ in 0..2 {
+			assert_err!(send(&email_client).await);
+		}
+		let error = send(&email_client).await.unwrap_err();
+
+		assert!(matches!(error, SendEmailError::CircuitOpen { .. }));
+	}
+
+	#[tokio::test]
+	async fn a_cancelled_probe_does_not_leave_the_circuit_half_open() {
+		let primary = MockServer::start().await;
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(500))
+			.up_to_n_times(2)
+			.mount(&primary)
+			.await;
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(50)))
+			.mount(&primary)
+			.await;
+		let email_client = EmailClient::new(email(), transport(&primary)).with_circuit_breaker(CircuitBreakerPolicy {
+			failure_threshold: 2,
+			open_duration: Duration::ZERO,
+		});
+		for _ in 0..2 {
+			assert_err!(send(&email_client).await);
+		}
+
+		// Dropped while the probe is in flight.
+		assert_err!(tokio::time::timeout(Duration::from_millis(10), send(&email_client)).await);
+
+		assert_eq!(email_client.transport_health()[0].circuit, CircuitState::Open);
+		assert_ok!(send(&email_client).await);
+		assert_eq!(email_client.transport_health()[0].circuit, CircuitState::Closed);
+	}
+}
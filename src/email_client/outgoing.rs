@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+use crate::domain::SubscriberEmail;
+
+/// A message to a single recipient; the sender is filled in by `EmailClient`.
+///
+/// Everything but the recipient, the subject and the two bodies is optional.
+#[derive(Clone, Debug)]
+pub struct OutgoingEmail {
+	pub(super) to: SubscriberEmail,
+	pub(super) subject: String,
+	pub(super) html_content: String,
+	pub(super) text_content: String,
+	pub(super) reply_to: Option<SubscriberEmail>,
+	pub(super) headers: Vec<(String, String)>,
+	pub(super) attachments: Vec<Attachment>,
+	pub(super) tag: Option<String>,
+	pub(super) metadata: BTreeMap<String, String>,
+	pub(super) message_stream: Option<String>,
+}
+
+impl OutgoingEmail {
+	pub fn new(
+		to: SubscriberEmail,
+		subject: impl Into<String>,
+		html_content: impl Into<String>,
+		text_content: impl Into<String>,
+	) -> Self {
+		Self {
+			to,
+			subject: subject.into(),
+			html_content: html_content.into(),
+			text_content: text_content.into(),
+			reply_to: None,
+			headers: Vec::new(),
+			attachments: Vec::new(),
+			tag: None,
+			metadata: BTreeMap::new(),
+			message_stream: None,
+		}
+	}
+
+	pub fn reply_to(mut self, reply_to: SubscriberEmail) -> Self {
+		self.reply_to = Some(reply_to);
+		self
+	}
+
+	pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+		self.headers.push((name.into(), value.into()));
+		self
+	}
+
+	pub fn attachment(mut self, attachment: Attachment) -> Self {
+		self.attachments.push(attachment);
+		self
+	}
+
+	/// Groups messages in Postmark's statistics. Sent as `X-PM-Tag` over SMTP.
+	pub fn tag(mut self, tag: impl Into<String>) -> Self {
+		self.tag = Some(tag.into());
+		self
+	}
+
+	/// Echoed back by Postmark's webhooks. Sent as `X-PM-Metadata-<key>` over SMTP.
+	pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+		self.metadata.insert(key.into(), value.into());
+		self
+	}
+
+	/// The Postmark message stream to send through, its default transactional stream if unset.
+	/// Sent as `X-PM-Message-Stream` over SMTP.
+	pub fn message_stream(mut self, message_stream: impl Into<String>) -> Self {
+		self.message_stream = Some(message_stream.into());
+		self
+	}
+}
+
+/// A file sent along with an email, either as a regular attachment or embedded in the HTML body.
+#[derive(Clone, Debug)]
+pub struct Attachment {
+	pub(super) name: String,
+	pub(super) content_type: String,
+	pub(super) content: Vec<u8>,
+	pub(super) content_id: Option<String>,
+}
+
+impl Attachment {
+	pub fn new(name: impl Into<String>, content_type: impl Into<String>, content: impl Into<Vec<u8>>) -> Self {
+		Self {
+			name: name.into(),
+			content_type: content_type.into(),
+			content: content.into(),
+			content_id: None,
+		}
+	}
+
+	/// An image the HTML body refers to as `<img src="cid:{content_id}">`.
+	pub fn inline(
+		content_id: impl Into<String>,
+		name: impl Into<String>,
+		content_type: impl Into<String>,
+		content: impl Into<Vec<u8>>,
+	) -> Self {
+		Self {
+			content_id: Some(content_id.into()),
+			..Self::new(name, content_type, content)
+		}
+	}
+
+	pub fn is_inline(&self) -> bool {
+		self.content_id.is_some()
+	}
+}
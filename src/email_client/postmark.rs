@@ -0,0 +1,575 @@
+use std::collections::BTreeMap;
+
+use base64::Engine;
+use reqwest::{
+	header::{HeaderMap, RETRY_AFTER},
+	Client,
+};
+use secrecy::{ExposeSecret, Secret};
+
+use super::{Attachment, DeliveryOutcome, Email, EmailTransport, SendEmailError};
+
+/// Delivers emails through Postmark's `/email` and `/email/batch` JSON APIs.
+pub struct PostmarkTransport {
+	http_client: reqwest::Client,
+	base_url: String,
+	authorization_token: Secret<String>,
+}
+
+impl PostmarkTransport {
+	pub fn new(
+		base_url: String,
+		authorization_token: Secret<String>,
+		timeout: std::time::Duration,
+	) -> Self {
+		let http_client = Client::builder()
+			.timeout(timeout)
+			.build()
+			.expect("Failed to build reqwest client");
+		Self {
+			http_client,
+			base_url,
+			authorization_token,
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+	fn name(&self) -> &'static str {
+		"postmark"
+	}
+
+	async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError> {
+		self.post("email", &SendEmailRequest::from(email)).await?;
+		Ok(())
+	}
+
+	/// Fetches the server's settings, which also checks the token.
+	async fn probe(&self) -> Result<(), SendEmailError> {
+		let response = self
+			.http_client
+			.get(format!("{}/server", self.base_url))
+			.header("X-Postmark-Server-Token", self.authorization_token.expose_secret())
+			.header("Accept", "application/json")
+			.send()
+			.await?;
+		let status = response.status();
+		if !status.is_success() {
+			return Err(SendEmailError::Rejected {
+				status: status.as_u16(),
+				retry_after: retry_after(response.headers()),
+			});
+		}
+		Ok(())
+	}
+
+	fn supports_batching(&self) -> bool {
+		true
+	}
+
+	async fn send_batch(&self, emails: &[Email<'_>]) -> Result<Vec<DeliveryOutcome>, SendEmailError> {
+		let requests: Vec<_> = emails.iter().map(SendEmailRequest::from).collect();
+		let results: Vec<BatchResult> = self.post("email/batch", &requests).await?.json().await?;
+		if results.len() != emails.len() {
+			return Err(SendEmailError::UnexpectedResponse(anyhow::anyhow!(
+				"Sent {} messages but received {} results.",
+				emails.len(),
+				results.len()
+			)));
+		}
+		Ok(results.iter().map(BatchResult::outcome).collect())
+	}
+}
+
+impl PostmarkTransport {
+	async fn post<Body: serde::Serialize + ?Sized>(
+		&self,
+		endpoint: &str,
+		body: &Body,
+	) -> Result<reqwest::Response, SendEmailError> {
+		let response = self
+			.http_client
+			.post(format!("{}/{}", self.base_url, endpoint))
+			.header("X-Postmark-Server-Token", self.authorization_token.expose_secret())
+			.json(body)
+			.send()
+			.await?;
+		let status = response.status();
+		if !status.is_success() {
+			return Err(SendEmailError::Rejected {
+				status: status.as_u16(),
+				retry_after: retry_after(response.headers()),
+			});
+		}
+		Ok(response)
+	}
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchResult {
+	error_code: i64,
+	#[serde(default)]
+	to: String,
+	#[serde(default)]
+	message: String,
+}
+
+impl BatchResult {
+	fn outcome(&self) -> DeliveryOutcome {
+		// See https://postmarkapp.com/developer/api/overview#error-codes
+		let outcome = match self.error_code {
+			0 => return DeliveryOutcome::Delivered,
+			// Service maintenance, account out of credits, rate limited.
+			100 | 405 | 429 => DeliveryOutcome::RetryLater,
+			_ => DeliveryOutcome::FailedPermanently,
+		};
+		tracing::warn!(
+			error_code = self.error_code,
+			error.message = %self.message,
+			recipient = %self.to,
+			"Postmark did not accept a message of the batch.",
+		);
+		outcome
+	}
+}
+
+/// Parses a `Retry-After` header given in seconds; HTTP dates are ignored.
+fn retry_after(headers: &HeaderMap) -> Option<std::time::Duration> {
+	headers
+		.get(RETRY_AFTER)?
+		.to_str()
+		.ok()?
+		.trim()
+		.parse()
+		.ok()
+		.map(std::time::Duration::from_secs)
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+	from: &'a str,
+	to: &'a str,
+	subject: &'a str,
+	html_body: &'a str,
+	text_body: &'a str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	reply_to: Option<&'a str>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	headers: Vec<EmailHeader<'a>>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	attachments: Vec<EmailAttachment<'a>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	tag: Option<&'a str>,
+	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
+	metadata: &'a BTreeMap<String, String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	message_stream: Option<&'a str>,
+}
+
+impl<'a> From<&'a Email<'a>> for SendEmailRequest<'a> {
+	fn from(email: &'a Email<'a>) -> Self {
+		let message = email.message;
+		Self {
+			from: email.from.as_ref(),
+			to: message.to.as_ref(),
+			subject: &message.subject,
+			html_body: &message.html_content,
+			text_body: &message.text_content,
+			reply_to: message.reply_to.as_ref().map(AsRef::as_ref),
+			headers: message
+				.headers
+				.iter()
+				.map(|(name, value)| EmailHeader { name, value })
+				.collect(),
+			attachments: message.attachments.iter().map(EmailAttachment::from).collect(),
+			tag: message.tag.as_deref(),
+			metadata: &message.metadata,
+			message_stream: message.message_stream.as_deref(),
+		}
+	}
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailHeader<'a> {
+	name: &'a str,
+	value: &'a str,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailAttachment<'a> {
+	name: &'a str,
+	/// Base64-encoded.
+	content: String,
+	content_type: &'a str,
+	/// Postmark embeds the attachment in the HTML body when set, as `cid:<content id>`.
+	#[serde(rename = "ContentID", skip_serializing_if = "Option::is_none")]
+	content_id: Option<String>,
+}
+
+impl<'a> From<&'a Attachment> for EmailAttachment<'a> {
+	fn from(attachment: &'a Attachment) -> Self {
+		Self {
+			name: &attachment.name,
+			content: base64::engine::general_purpose::STANDARD.encode(&attachment.content),
+			content_type: &attachment.content_type,
+			content_id: attachment.content_id.as_ref().map(|content_id| format!("cid:{}", content_id)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use claim::{assert_err, assert_ok};
+	use fake::{faker::{internet::en::SafeEmail, lorem::en::{Paragraph, Sentence}}, Fake, Faker};
+	use secrecy::Secret;
+	use wiremock::{http::Method, matchers::{any, body_partial_json, header, header_exists, method, path}, Mock, MockServer, Request, ResponseTemplate};
+	use crate::{
+		domain::SubscriberEmail,
+		email_client::{Attachment, DeliveryOutcome, EmailClient, OutgoingEmail, RetryPolicy},
+	};
+
+	use super::PostmarkTransport;
+
+	fn subject() -> String {
+		Sentence(1..2).fake()
+	}
+
+	fn content() -> String {
+		Paragraph(1..10).fake()
+	}
+
+	fn email() -> SubscriberEmail {
+		SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+	}
+
+	fn email_client(base_url: String) -> EmailClient {
+		EmailClient::new(
+			email(),
+			PostmarkTransport::new(
+				base_url,
+				Secret::new(Faker.fake()),
+				std::time::Duration::from_millis(100),
+			),
+		)
+	}
+
+	#[tokio::test]
+	async fn send_email_fires_a_request_to_base_url() {
+		let mock_server = MockServer::start().await;
+		let email_client = email_client(mock_server.uri());
+
+		Mock::given(header_exists("X-Postmark-Server-Token"))
+			.and(header("Content-Type", "application/json"))
+			.and(path("/email"))
+			.and(method(Method::POST))
+			.and(SendEmailBodyMatcher)
+			.respond_with(ResponseTemplate::new(200))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+
+		let _ = email_client
+			.send_email(&email(), &subject(), &content(), &content())
+			.await;
+	}
+
+	struct SendEmailBodyMatcher;
+
+	impl wiremock::Match for SendEmailBodyMatcher {
+		fn matches(&self, request: &Request) -> bool {
+			let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+			if let Ok(body) = result {
+				body.get("From").is_some() 
+				&& body.get("To").is_some()
+				&& body.get("Subject").is_some()
+				&& body.get("HtmlBody").is_some()
+				&& body.get("TextBody").is_some()
+			} else {
+				false
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn send_includes_the_custom_headers_in_the_body() {
+		let mock_server = MockServer::start().await;
+		let email_client = email_client(mock_server.uri());
+
+		Mock::given(path("/email"))
+			.and(body_partial_json(serde_json::json!({
+				"Headers": [{"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"}]
+			})))
+			.respond_with(ResponseTemplate::new(200))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+
+		let message = OutgoingEmail::new(email(), subject(), content(), content())
+			.header("List-Unsubscribe-Post", "List-Unsubscribe=One-Click");
+		let outcome = email_client.send(&message).await;
+
+		assert_ok!(outcome);
+	}
+
+	#[tokio::test]
+	async fn send_maps_attachments_and_postmark_fields_to_the_body() {
+		let mock_server = MockServer::start().await;
+		let email_client = email_client(mock_server.uri());
+		let reply_to = email();
+
+		Mock::given(path("/email"))
+			.and(body_partial_json(serde_json::json!({
+				"ReplyTo": reply_to.as_ref(),
+				"Attachments": [
+					{"Name": "issue-42.pdf", "Content": "JVBERi0xLjc=", "ContentType": "application/pdf"},
+					{"Name": "logo.png", "Content": "iVBORw==", "ContentType": "image/png", "ContentID": "cid:logo"},
+				],
+				"Tag": "newsletter",
+				"Metadata": {"newsletter_issue_id": "42"},
+				"MessageStream": "broadcast",
+			})))
+			.respond_with(ResponseTemplate::new(200))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+
+		let message = OutgoingEmail::new(email(), subject(), content(), content())
+			.reply_to(reply_to.clone())
+			.attachment(Attachment::new("issue-42.pdf", "application/pdf", b"%PDF-1.7".to_vec()))
+			.attachment(Attachment::inline("logo", "logo.png", "image/png", b"\x89PNG".to_vec()))
+			.tag("newsletter")
+			.metadata("newsletter_issue_id", "42")
+			.message_stream("broadcast");
+		let outcome = email_client.send(&message).await;
+
+		assert_ok!(outcome);
+	}
+
+	#[tokio::test]
+	async fn send_email_omits_the_optional_fields() {
+		let mock_server = MockServer::start().await;
+		let email_client = email_client(mock_server.uri());
+		Mock::given(path("/email"))
+			.respond_with(ResponseTemplate::new(200))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+
+		assert_ok!(email_client.send_email(&email(), &subject(), &content(), &content()).await);
+
+		let request = &mock_server.received_requests().await.unwrap()[0];
+		let body: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+		let mut fields: Vec<_> = body.keys().map(String::as_str).collect();
+		fields.sort();
+		assert_eq!(fields, ["From", "HtmlBody", "Subject", "TextBody", "To"]);
+	}
+
+	#[tokio::test]
+	async fn send_email_succeeds_if_the_server_returns_200() {
+		let mock_server = MockServer::start().await;
+		let email_client = email_client(mock_server.uri());
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(200))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+
+		let outcome = email_client
+			.send_email(&email(), &subject(), &content(), &content())
+			.await;
+
+		assert_ok!(outcome);
+	}
+
+	#[tokio::test]
+	async fn send_email_fails_if_the_server_returns_500() {
+		let mock_server = MockServer::start().await;
+		let email_client = email_client(mock_server.uri());
+
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(500))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+
+		let outcome = email_client
+			.send_email(&email(), &subject(), &content(), &content())
+			.await;
+
+		assert_err!(outcome);
+	}
+
+	#[tokio::test]
+	async fn send_email_times_out_if_the_server_takes_too_long() {
+		let mock_server = MockServer::start().await;
+		let sender = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+		let email_client = EmailClient::new(
+			sender,
+			PostmarkTransport::new(
+				mock_server.uri(),
+				Secret::new(Faker.fake()),
+				std::time::Duration::from_millis(100),
+			),
+		);
+
+		let subscriber_email = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+		let subject: String = Sentence(1..2).fake();
+		let content: String = Paragraph(1..10).fake();
+
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(200)
+			.set_delay(std::time::Duration::from_secs(180)))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+
+		let outcome = email_client
+			.send_email(&subscriber_email, &subject, &content, &content)
+			.await;
+
+		assert_err!(outcome);
+	}
+
+	fn batch_response(request: &Request) -> ResponseTemplate {
+		let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+		let results: Vec<_> = messages
+			.iter()
+			.map(|_| serde_json::json!({"ErrorCode": 0, "Message": "OK"}))
+			.collect();
+		ResponseTemplate::new(200).set_body_json(results)
+	}
+
+	#[tokio::test]
+	async fn send_batch_maps_each_result_to_an_outcome() {
+		let mock_server = MockServer::start().await;
+		let email_client = email_client(mock_server.uri());
+		Mock::given(path("/email/batch"))
+			.and(method(Method::POST))
+			.respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+				{"ErrorCode": 0, "Message": "OK"},
+				{"ErrorCode": 406, "Message": "Inactive recipient"},
+				{"ErrorCode": 429, "Message": "Rate limit exceeded"},
+			])))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+		let recipients = [email(), email(), email()];
+		let emails: Vec<_> = recipients
+			.iter()
+			.map(|recipient| OutgoingEmail::new(recipient.clone(), "Subject", "<p>Body</p>", "Body"))
+			.collect();
+
+		let outcomes = email_client.send_batch(&emails).await.unwrap();
+
+		assert_eq!(
+			outcomes,
+			vec![
+				DeliveryOutcome::Delivered,
+				DeliveryOutcome::FailedPermanently,
+				DeliveryOutcome::RetryLater,
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn send_batch_splits_large_batches_into_chunks_of_500() {
+		let mock_server = MockServer::start().await;
+		let email_client = email_client(mock_server.uri());
+		Mock::given(path("/email/batch"))
+			.respond_with(batch_response)
+			.expect(2)
+			.mount(&mock_server)
+			.await;
+		let recipient = email();
+		let emails: Vec<_> = (0..501)
+			.map(|_| OutgoingEmail::new(recipient.clone(), "Subject", "<p>Body</p>", "Body"))
+			.collect();
+
+		let outcomes = email_client.send_batch(&emails).await.unwrap();
+
+		assert_eq!(outcomes.len(), 501);
+		assert!(outcomes.iter().all(|outcome| *outcome == DeliveryOutcome::Delivered));
+	}
+
+	#[tokio::test]
+	async fn send_batch_does_not_resend_a_batch_that_timed_out() {
+		let mock_server = MockServer::start().await;
+		let email_client = email_client(mock_server.uri()).with_retry_policy(RetryPolicy {
+			max_attempts: 3,
+			base_delay: std::time::Duration::from_millis(1),
+			max_delay: std::time::Duration::from_millis(1),
+			jitter: 0.,
+		});
+		Mock::given(path("/email/batch"))
+			.respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180)))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+		let emails = [OutgoingEmail::new(email(), "Subject", "<p>Body</p>", "Body")];
+
+		assert_err!(email_client.send_batch(&emails).await);
+	}
+
+	#[tokio::test]
+	async fn send_batch_retries_a_batch_the_provider_refused() {
+		let mock_server = MockServer::start().await;
+		let email_client = email_client(mock_server.uri()).with_retry_policy(RetryPolicy {
+			max_attempts: 3,
+			base_delay: std::time::Duration::from_millis(1),
+			max_delay: std::time::Duration::from_millis(1),
+			jitter: 0.,
+		});
+		Mock::given(path("/email/batch"))
+			.respond_with(ResponseTemplate::new(503))
+			.up_to_n_times(1)
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+		Mock::given(path("/email/batch"))
+			.respond_with(batch_response)
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+		let emails = [OutgoingEmail::new(email(), "Subject", "<p>Body</p>", "Body")];
+
+		assert_ok!(email_client.send_batch(&emails).await);
+	}
+
+	#[tokio::test]
+	async fn send_batch_fails_if_the_number_of_results_does_not_match() {
+		let mock_server = MockServer::start().await;
+		let email_client = email_client(mock_server.uri());
+		Mock::given(path("/email/batch"))
+			.respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+		let recipient = email();
+		let emails = [OutgoingEmail::new(recipient, "Subject", "<p>Body</p>", "Body")];
+
+		let error = email_client.send_batch(&emails).await.unwrap_err();
+
+		assert!(error.was_accepted());
+	}
+
+	#[tokio::test]
+	async fn send_batch_reports_an_unreadable_success_as_accepted() {
+		let mock_server = MockServer::start().await;
+		let email_client = email_client(mock_server.uri());
+		Mock::given(path("/email/batch"))
+			.respond_with(ResponseTemplate::new(200).set_body_string("<html>Accepted</html>"))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+		let emails = [OutgoingEmail::new(email(), "Subject", "<p>Body</p>", "Body")];
+
+		let error = email_client.send_batch(&emails).await.unwrap_err();
+
+		assert!(error.was_accepted());
+	}
+}
@@ -0,0 +1,149 @@
+use std::{
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// How long to wait before checking again when every in-flight slot is taken.
+const IN_FLIGHT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Throttles the requests we send to an email provider.
+///
+/// A token bucket caps the number of messages per second, allowing bursts of up to one
+/// second worth of messages, and a semaphore caps the number of requests in flight.
+pub struct RateLimiter {
+	messages_per_second: f64,
+	burst: f64,
+	bucket: Mutex<Bucket>,
+	in_flight: Arc<Semaphore>,
+}
+
+struct Bucket {
+	tokens: f64,
+	refilled_at: Instant,
+}
+
+/// Holds one in-flight slot until dropped.
+pub struct SendPermit {
+	_in_flight: OwnedSemaphorePermit,
+}
+
+impl RateLimiter {
+	pub fn new(messages_per_second: f64, max_in_flight_requests: usize) -> Self {
+		let burst = messages_per_second.ceil().max(1.);
+		Self {
+			messages_per_second,
+			burst,
+			bucket: Mutex::new(Bucket {
+				tokens: burst,
+				refilled_at: Instant::now(),
+			}),
+			in_flight: Arc::new(Semaphore::new(max_in_flight_requests.max(1))),
+		}
+	}
+
+	/// The most messages a single request may carry.
+	pub fn burst(&self) -> usize {
+		self.burst as usize
+	}
+
+	/// Returns how many messages can be sent right away, or how long to wait until
+	/// at least one can.
+	pub fn capacity(&self) -> Result<usize, Duration> {
+		if self.in_flight.available_permits() == 0 {
+			return Err(IN_FLIGHT_POLL_INTERVAL);
+		}
+		let bucket = self.refill();
+		if bucket.tokens < 1. {
+			return Err(self.time_until(&bucket, 1.));
+		}
+		Ok(bucket.tokens as usize)
+	}
+
+	/// Waits for an in-flight slot and for enough tokens to send `n_messages`.
+	///
+	/// Requests carrying more than `burst()` messages are charged `burst()` tokens.
+	pub async fn acquire(&self, n_messages: usize) -> SendPermit {
+		let permit = self
+			.in_flight
+			.clone()
+			.acquire_owned()
+			.await
+			.expect("The in-flight semaphore is never closed.");
+		let tokens = (n_messages as f64).min(self.burst);
+		loop {
+			let wait = {
+				let mut bucket = self.refill();
+				if bucket.tokens >= tokens {
+					bucket.tokens -= tokens;
+					break;
+				}
+				self.time_until(&bucket, tokens)
+			};
+			tokio::time::sleep(wait).await;
+		}
+		SendPermit { _in_flight: permit }
+	}
+
+	fn refill(&self) -> std::sync::MutexGuard<'_, Bucket> {
+		let mut bucket = self.bucket.lock().expect("The rate limiter lock is poisoned.");
+		let now = Instant::now();
+		let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+		bucket.tokens = (bucket.tokens + elapsed * self.messages_per_second).min(self.burst);
+		bucket.refilled_at = now;
+		bucket
+	}
+
+	fn time_until(&self, bucket: &Bucket, tokens: f64) -> Duration {
+		Duration::from_secs_f64((tokens - bucket.tokens) / self.messages_per_second)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use claim::{assert_err, assert_ok, assert_ok_eq};
+
+	use super::RateLimiter;
+
+	#[test]
+	fn a_full_bucket_allows_one_second_worth_of_messages() {
+		let limiter = RateLimiter::new(10., 2);
+
+		assert_ok_eq!(limiter.capacity(), 10);
+	}
+
+	#[tokio::test]
+	async fn an_empty_bucket_reports_how_long_to_wait() {
+		let limiter = RateLimiter::new(10., 2);
+
+		drop(limiter.acquire(10).await);
+
+		let wait = limiter.capacity().unwrap_err();
+		assert!(wait > Duration::ZERO && wait <= Duration::from_millis(100));
+	}
+
+	#[tokio::test]
+	async fn acquire_waits_for_the_bucket_to_refill() {
+		let limiter = RateLimiter::new(20., 1);
+		drop(limiter.acquire(20).await);
+
+		let start = Instant::now();
+		drop(limiter.acquire(1).await);
+
+		assert!(start.elapsed() >= Duration::from_millis(40));
+	}
+
+	#[tokio::test]
+	async fn in_flight_requests_are_capped() {
+		let limiter = RateLimiter::new(100., 1);
+
+		let permit = limiter.acquire(1).await;
+		assert_err!(limiter.capacity());
+
+		drop(permit);
+		assert_ok!(limiter.capacity());
+	}
+}
@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use super::SendEmailError;
+
+/// How often and how patiently `EmailClient` retries transient delivery failures.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+	pub max_attempts: u32,
+	pub base_delay: Duration,
+	pub max_delay: Duration,
+	/// Fraction of each backoff delay, between 0 and 1, that is randomised.
+	pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_attempts: 1,
+			base_delay: Duration::ZERO,
+			max_delay: Duration::ZERO,
+			jitter: 0.,
+		}
+	}
+}
+
+impl RetryPolicy {
+	/// Returns how long to wait before the next attempt, or `None` if `error` should be
+	/// returned to the caller straight away.
+	pub fn delay_before_retry(&self, attempt: u32, error: &SendEmailError) -> Option<Duration> {
+		if attempt >= self.max_attempts || !error.is_transient() {
+			return None;
+		}
+		match error.retry_after() {
+			// The provider told us when to come back: wait for it unless that exceeds our cap,
+			// in which case it is better to give up and let the caller reschedule.
+			Some(retry_after) if retry_after > self.max_delay => None,
+			Some(retry_after) => Some(retry_after),
+			None => Some(self.backoff(attempt)),
+		}
+	}
+
+	fn backoff(&self, attempt: u32) -> Duration {
+		let exponential = self
+			.base_delay
+			.saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+			.min(self.max_delay);
+		let jitter = self.jitter.clamp(0., 1.);
+		exponential.mul_f64(1. - jitter * rand::thread_rng().gen::<f64>())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use claim::{assert_err, assert_ok};
+	use fake::{faker::internet::en::SafeEmail, Fake, Faker};
+	use secrecy::Secret;
+	use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
+
+	use crate::{
+		domain::SubscriberEmail,
+		email_client::{EmailClient, PostmarkTransport},
+	};
+
+	use super::RetryPolicy;
+
+	fn email() -> SubscriberEmail {
+		SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+	}
+
+	fn retry_policy() -> RetryPolicy {
+		RetryPolicy {
+			max_attempts: 3,
+			base_delay: Duration::from_millis(1),
+			max_delay: Duration::from_secs(2),
+			jitter: 0.5,
+		}
+	}
+
+	fn email_client(base_url: String) -> EmailClient {
+		EmailClient::new(
+			email(),
+			PostmarkTransport::new(base_url, Secret::new(Faker.fake()), Duration::from_millis(100)),
+		)
+		.with_retry_policy(retry_policy())
+	}
+
+	async fn send(email_client: &EmailClient) -> Result<(), crate::email_client::SendEmailError> {
+		email_client.send_email(&email(), "Subject", "<p>Body</p>", "Body").await
+	}
+
+	#[test]
+	fn backoff_grows_exponentially_up_to_the_cap() {
+		let policy = RetryPolicy {
+			max_attempts: 10,
+			base_delay: Duration::from_millis(100),
+			max_delay: Duration::from_millis(500),
+			jitter: 0.,
+		};
+		assert_eq!(policy.backoff(1), Duration::from_millis(100));
+		assert_eq!(policy.backoff(2), Duration::from_millis(200));
+		assert_eq!(policy.backoff(3), Duration::from_millis(400));
+		assert_eq!(policy.backoff(4), Duration::from_millis(500));
+	}
+
+	#[test]
+	fn jitter_never_extends_the_backoff() {
+		let policy = RetryPolicy {
+			jitter: 1.,
+			..retry_policy()
+		};
+		for attempt in 1..5 {
+			assert!(policy.backoff(attempt) <= Duration::from_millis(1 << (attempt - 1)));
+		}
+	}
+
+	#[tokio::test]
+	async fn server_errors_are_retried_until_the_request_succeeds() {
+		let mock_server = MockServer::start().await;
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(500))
+			.up_to_n_times(2)
+			.expect(2)
+			.mount(&mock_server)
+			.await;
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(200))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+
+		assert_ok!(send(&email_client(mock_server.uri())).await);
+	}
+
+	#[tokio::test]
+	async fn server_errors_are_retried_at_most_max_attempts_times() {
+		let mock_server = MockServer::start().await;
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(503))
+			.expect(3)
+			.mount(&mock_server)
+			.await;
+
+		assert_err!(send(&email_client(mock_server.uri())).await);
+	}
+
+	#[tokio::test]
+	async fn client_errors_are_not_retried() {
+		let mock_server = MockServer::start().await;
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(422))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+
+		assert_err!(send(&email_client(mock_server.uri())).await);
+	}
+
+	#[tokio::test]
+	async fn timeouts_are_retried() {
+		let mock_server = MockServer::start().await;
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(180)))
+			.expect(3)
+			.mount(&mock_server)
+			.await;
+
+		assert_err!(send(&email_client(mock_server.uri())).await);
+	}
+
+	#[tokio::test]
+	async fn rate_limited_requests_honor_retry_after() {
+		let mock_server = MockServer::start().await;
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+			.up_to_n_times(1)
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(200))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+
+		let start = std::time::Instant::now();
+		assert_ok!(send(&email_client(mock_server.uri())).await);
+		assert!(start.elapsed() >= Duration::from_secs(1));
+	}
+
+	#[tokio::test]
+	async fn a_retry_after_beyond_the_cap_is_not_waited_for() {
+		let mock_server = MockServer::start().await;
+		Mock::given(any())
+			.respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+			.expect(1)
+			.mount(&mock_server)
+			.await;
+
+		assert_err!(send(&email_client(mock_server.uri())).await);
+	}
+}
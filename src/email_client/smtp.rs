@@ -0,0 +1,58 @@
+use lettre::{
+	transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+use secrecy::ExposeSecret;
+
+use crate::configuration::{SmtpSettings, SmtpTlsSettings};
+
+use super::{to_mime_message, Email, EmailTransport, SendEmailError};
+
+/// Delivers emails through a generic SMTP relay.
+pub struct SmtpTransport {
+	transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+	pub fn new(
+		settings: &SmtpSettings,
+		timeout: std::time::Duration,
+	) -> Result<Self, lettre::transport::smtp::Error> {
+		let builder = match settings.tls {
+			SmtpTlsSettings::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?,
+			SmtpTlsSettings::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+			SmtpTlsSettings::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host),
+		};
+		let mut builder = builder.port(settings.port).timeout(Some(timeout));
+		if !settings.username.is_empty() {
+			builder = builder.credentials(Credentials::new(
+				settings.username.clone(),
+				settings.password.expose_secret().clone(),
+			));
+		}
+		Ok(Self {
+			transport: builder.build(),
+		})
+	}
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+	fn name(&self) -> &'static str {
+		"smtp"
+	}
+
+	async fn send(&self, email: &Email<'_>) -> Result<(), SendEmailError> {
+		let message = to_mime_message(email)?;
+		self.transport.send(message).await?;
+		Ok(())
+	}
+
+	async fn probe(&self) -> Result<(), SendEmailError> {
+		if !self.transport.test_connection().await? {
+			return Err(SendEmailError::UnexpectedResponse(anyhow::anyhow!(
+				"The SMTP relay did not answer the connection test."
+			)));
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,122 @@
+use std::{fmt::Write, path::Path};
+
+use anyhow::Context;
+use minijinja::{context, AutoEscape, Environment, Error, Output, State, Value};
+
+/// Every template is rendered once at startup, so a broken one fails `Application::build`
+/// instead of the first send.
+const TEMPLATES: [&str; 2] = ["confirmation", "newsletter"];
+
+pub struct RenderedEmail {
+	pub html: String,
+	pub text: String,
+}
+
+/// Named email templates, each with an `.html` and a `.txt` variant.
+///
+/// Values interpolated into `.html` templates are HTML-escaped unless marked `safe`.
+pub struct EmailTemplates {
+	environment: Environment<'static>,
+}
+
+impl EmailTemplates {
+	pub fn load(directory: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+		let directory = directory.as_ref();
+		let mut environment = Environment::new();
+		environment.set_loader(minijinja::path_loader(directory));
+		environment.set_formatter(html_formatter);
+		environment.set_trim_blocks(true);
+		let templates = Self { environment };
+		for name in TEMPLATES {
+			templates.render(name, context! {}).with_context(|| {
+				format!("Failed to load the `{}` email template from {}.", name, directory.display())
+			})?;
+		}
+		Ok(templates)
+	}
+
+	pub fn render(&self, name: &str, context: Value) -> Result<RenderedEmail, Error> {
+		let html = self
+			.environment
+			.get_template(&format!("{}.html", name))?
+			.render(&context)?;
+		let text = self
+			.environment
+			.get_template(&format!("{}.txt", name))?
+			.render(&context)?;
+		Ok(RenderedEmail { html, text })
+	}
+}
+
+// minijinja's default HTML escaping also encodes `/`, which breaks the links mail clients
+// detect in message bodies. Escape the characters that matter and leave URLs readable.
+fn html_formatter(out: &mut Output, state: &State, value: &Value) -> Result<(), Error> {
+	match (state.auto_escape(), value.as_str()) {
+		(AutoEscape::Html, Some(s)) if !value.is_safe() => {
+			for c in s.chars() {
+				match c {
+					'&' => out.write_str("&amp;"),
+					'<' => out.write_str("&lt;"),
+					'>' => out.write_str("&gt;"),
+					'"' => out.write_str("&quot;"),
+					'\'' => out.write_str("&#x27;"),
+					c => out.write_char(c),
+				}
+				.map_err(Error::from)?;
+			}
+			Ok(())
+		}
+		_ => minijinja::escape_formatter(out, state, value),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use claim::assert_err;
+	use minijinja::context;
+
+	use super::EmailTemplates;
+
+	fn templates() -> EmailTemplates {
+		EmailTemplates::load("templates/email").unwrap()
+	}
+
+	#[test]
+	fn html_variants_escape_interpolated_values() {
+		let rendered = templates()
+			.render(
+				"confirmation",
+				context! { confirmation_link => "https://example.com/?a=1&b=\"><script>" },
+			)
+			.unwrap();
+
+		assert!(rendered.html.contains("https://example.com/?a=1&amp;b=&quot;&gt;&lt;script&gt;"));
+		assert!(rendered.text.contains("https://example.com/?a=1&b=\"><script>"));
+	}
+
+	#[test]
+	fn newsletter_bodies_are_wrapped_in_the_layout() {
+		let rendered = templates()
+			.render(
+				"newsletter",
+				context! {
+					subject => "Issue #1",
+					html_content => "<p>Hello</p>",
+					text_content => "Hello",
+					unsubscribe_link => "https://example.com/unsubscribe",
+				},
+			)
+			.unwrap();
+
+		assert!(rendered.html.contains("<title>Issue #1</title>"));
+		assert!(rendered.html.contains("<p>Hello</p>"));
+		assert!(rendered.html.contains("href=\"https://example.com/unsubscribe\""));
+		assert!(rendered.text.contains("Hello"));
+		assert!(rendered.text.contains("Unsubscribe: https://example.com/unsubscribe"));
+	}
+
+	#[test]
+	fn loading_fails_if_a_template_is_missing() {
+		assert_err!(EmailTemplates::load("does/not/exist").map(|_| ()));
+	}
+}
@@ -0,0 +1,100 @@
+use actix_web::{
+	body::{BoxBody, MessageBody},
+	dev::{Payload, ServiceRequest, ServiceResponse},
+	error::ErrorUnprocessableEntity,
+	web, HttpMessage,
+};
+use actix_web_lab::middleware::Next;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+
+use super::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::{authentication::UserId, utils::{e400, e500}};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+pub struct IdempotencyTtl(pub std::time::Duration);
+
+/// Replays the saved response for requests carrying an `Idempotency-Key`
+/// header that has already been processed for the same caller.
+///
+/// Requests without the header are passed through untouched. Server errors
+/// are not persisted, so a retry after a 5xx is processed again. Reusing a key
+/// for a different request is rejected with a 422.
+pub async fn honor_idempotency_key(
+	mut req: ServiceRequest,
+	next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+	let idempotency_key: IdempotencyKey = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+		None => return next.call(req).await.map(ServiceResponse::map_into_boxed_body),
+		Some(value) => value
+			.to_str()
+			.map_err(e400)?
+			.to_owned()
+			.try_into()
+			.map_err(e400)?,
+	};
+	let connection_pool = req
+		.app_data::<web::Data<Pool<Postgres>>>()
+		.expect("The connection pool is not registered as application data.")
+		.clone();
+	let ttl = req
+		.app_data::<web::Data<IdempotencyTtl>>()
+		.expect("The idempotency TTL is not registered as application data.")
+		.0;
+	let caller = caller(&req);
+	let request_hash = request_hash(&mut req).await?;
+
+	let transaction = match try_processing(&connection_pool, &idempotency_key, &caller, &request_hash, ttl)
+		.await
+		.map_err(e500)?
+	{
+		NextAction::StartProcessing(transaction) => transaction,
+		NextAction::ReturnSavedResponse(saved_response) => {
+			return Ok(req.into_response(saved_response));
+		}
+		NextAction::RejectReusedKey => {
+			return Err(ErrorUnprocessableEntity(
+				"The Idempotency-Key was already used for a different request.",
+			));
+		}
+	};
+
+	let response = next.call(req).await?.map_into_boxed_body();
+	if response.status().is_server_error() {
+		// Dropping the transaction releases the key for the next attempt.
+		return Ok(response);
+	}
+	let (request, response) = response.into_parts();
+	let response = save_response(transaction, &idempotency_key, &caller, response)
+		.await
+		.map_err(e500)?;
+	Ok(ServiceResponse::new(request, response))
+}
+
+// Authenticated requests are scoped to the user, anonymous ones to the address of the peer.
+// Not `realip_remote_addr`: `Forwarded` and `X-Forwarded-For` are whatever the client says.
+fn caller(req: &ServiceRequest) -> String {
+	if let Some(user_id) = req.extensions().get::<UserId>() {
+		return format!("user:{}", user_id);
+	}
+	let address = req
+		.peer_addr()
+		.map_or_else(|| "unknown".to_owned(), |address| address.ip().to_string());
+	format!("address:{}", address)
+}
+
+/// Hashes the method, path and body, then puts the body back for the handler.
+async fn request_hash(req: &mut ServiceRequest) -> Result<Vec<u8>, actix_web::Error> {
+	let body = req.extract::<web::Bytes>().await?;
+	let mut hasher = Sha256::new();
+	hasher.update(req.method().as_str());
+	hasher.update(b" ");
+	hasher.update(req.path());
+	hasher.update(b"\n");
+	hasher.update(&body);
+	let (_, mut payload) = actix_http::h1::Payload::create(true);
+	payload.unread_data(body);
+	req.set_payload(Payload::from(payload));
+	Ok(hasher.finalize().to_vec())
+}
@@ -0,0 +1,7 @@
+mod key;
+mod middleware;
+mod persistence;
+
+pub use key::IdempotencyKey;
+pub use middleware::*;
+pub use persistence::{get_saved_response, save_response, try_processing, NextAction};
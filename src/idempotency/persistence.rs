@@ -0,0 +1,186 @@
+use actix_web::{body::to_bytes, http::StatusCode, HttpResponse};
+use sqlx::{postgres::PgHasArrayType, Pool, Postgres, Transaction};
+
+use super::IdempotencyKey;
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+	name: String,
+	value: Vec<u8>,
+}
+
+impl PgHasArrayType for HeaderPairRecord {
+	fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+		sqlx::postgres::PgTypeInfo::with_name("_header_pair")
+	}
+}
+
+#[allow(clippy::large_enum_variant)]
+pub enum NextAction {
+	StartProcessing(Transaction<'static, Postgres>),
+	ReturnSavedResponse(HttpResponse),
+	/// The key was already used for a request with another method, path or body.
+	RejectReusedKey,
+}
+
+#[tracing::instrument(
+	name = "Retrieve a saved response for an idempotency key",
+	skip(connection_pool, idempotency_key)
+)]
+pub async fn get_saved_response(
+	connection_pool: &Pool<Postgres>,
+	idempotency_key: &IdempotencyKey,
+	caller: &str,
+) -> Result<Option<HttpResponse>, anyhow::Error> {
+	let saved_response = sqlx::query!(
+		r#"
+		SELECT
+			response_status_code as "response_status_code!",
+			response_headers as "response_headers!: Vec<HeaderPairRecord>",
+			response_body as "response_body!"
+		FROM idempotency
+		WHERE
+			caller = $1 AND
+			idempotency_key = $2
+		"#,
+		caller,
+		idempotency_key.as_ref()
+	)
+	.fetch_optional(connection_pool)
+	.await?;
+	if let Some(r) = saved_response {
+		let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
+		let mut response = HttpResponse::build(status_code);
+		for HeaderPairRecord { name, value } in r.response_headers {
+			response.append_header((name, value));
+		}
+		Ok(Some(response.body(r.response_body)))
+	} else {
+		Ok(None)
+	}
+}
+
+/// Claims `idempotency_key` for `caller`, recording `request_hash` to tell a retry of the
+/// request apart from another request reusing the key.
+///
+/// The returned transaction holds a row lock on the claimed key: a concurrent
+/// request with the same key blocks on its own insert until the transaction is
+/// either committed by `save_response` or rolled back by being dropped.
+#[tracing::instrument(
+	name = "Try processing a request with an idempotency key",
+	skip(connection_pool, idempotency_key, request_hash)
+)]
+pub async fn try_processing(
+	connection_pool: &Pool<Postgres>,
+	idempotency_key: &IdempotencyKey,
+	caller: &str,
+	request_hash: &[u8],
+	ttl: std::time::Duration,
+) -> Result<NextAction, anyhow::Error> {
+	delete_expired_entries(connection_pool, ttl).await?;
+
+	let mut transaction = connection_pool.begin().await?;
+	let n_inserted_rows = sqlx::query!(
+		r#"
+		INSERT INTO idempotency (
+			caller,
+			idempotency_key,
+			request_hash,
+			created_at
+		)
+		VALUES ($1, $2, $3, now())
+		ON CONFLICT DO NOTHING
+		"#,
+		caller,
+		idempotency_key.as_ref(),
+		request_hash
+	)
+	.execute(&mut *transaction)
+	.await?
+	.rows_affected();
+	if n_inserted_rows > 0 {
+		Ok(NextAction::StartProcessing(transaction))
+	} else {
+		let saved_request_hash = sqlx::query_scalar!(
+			"SELECT request_hash FROM idempotency WHERE caller = $1 AND idempotency_key = $2",
+			caller,
+			idempotency_key.as_ref()
+		)
+		.fetch_one(connection_pool)
+		.await?;
+		if saved_request_hash.is_some_and(|saved_request_hash| saved_request_hash != request_hash) {
+			return Ok(NextAction::RejectReusedKey);
+		}
+		let saved_response = get_saved_response(connection_pool, idempotency_key, caller)
+			.await?
+			.ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
+		Ok(NextAction::ReturnSavedResponse(saved_response))
+	}
+}
+
+#[tracing::instrument(
+	name = "Save the response for an idempotency key",
+	skip(transaction, idempotency_key, http_response)
+)]
+pub async fn save_response(
+	mut transaction: Transaction<'static, Postgres>,
+	idempotency_key: &IdempotencyKey,
+	caller: &str,
+	http_response: HttpResponse,
+) -> Result<HttpResponse, anyhow::Error> {
+	let (response_head, body) = http_response.into_parts();
+	let body = to_bytes(body).await.map_err(|e| anyhow::anyhow!("{}", e))?;
+	let status_code = response_head.status().as_u16() as i16;
+	let headers = response_head
+		.headers()
+		.iter()
+		.map(|(name, value)| HeaderPairRecord {
+			name: name.as_str().to_owned(),
+			value: value.as_bytes().to_owned(),
+		})
+		.collect::<Vec<_>>();
+	sqlx::query_unchecked!(
+		r#"
+		UPDATE idempotency
+		SET
+			response_status_code = $3,
+			response_headers = $4,
+			response_body = $5
+		WHERE
+			caller = $1 AND
+			idempotency_key = $2
+		"#,
+		caller,
+		idempotency_key.as_ref(),
+		status_code,
+		headers,
+		body.as_ref()
+	)
+	.execute(&mut *transaction)
+	.await?;
+	transaction.commit().await?;
+
+	let http_response = response_head.set_body(body).map_into_boxed_body();
+	Ok(http_response)
+}
+
+#[tracing::instrument(
+	name = "Delete expired idempotency keys",
+	skip(connection_pool)
+)]
+async fn delete_expired_entries(
+	connection_pool: &Pool<Postgres>,
+	ttl: std::time::Duration,
+) -> Result<(), anyhow::Error> {
+	sqlx::query!(
+		r#"
+		DELETE FROM idempotency
+		WHERE created_at < now() - make_interval(secs => $1)
+		"#,
+		ttl.as_secs_f64()
+	)
+	.execute(connection_pool)
+	.await?;
+	Ok(())
+}
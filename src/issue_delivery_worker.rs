@@ -0,0 +1,349 @@
+use std::{
+	collections::{hash_map::Entry, HashMap},
+	time::Duration,
+};
+
+use minijinja::context;
+use secrecy::Secret;
+use sqlx::{Pool, Postgres, Transaction};
+use tracing::Span;
+use uuid::Uuid;
+
+use crate::{
+	configuration::Settings,
+	domain::SubscriberEmail,
+	email_client::{DeliveryOutcome, EmailClient, OutgoingEmail},
+	email_templates::EmailTemplates,
+	startup::get_connection_pool,
+	unsubscribe::unsubscribe_link,
+};
+
+const MAX_DELIVERY_RETRIES: i16 = 5;
+
+pub enum ExecutionOutcome {
+	TaskCompleted,
+	EmptyQueue,
+	/// The email provider's rate limit is exhausted; try again after the given delay.
+	Throttled(Duration),
+}
+
+/// `email_client` should be shared with the API so both respect the same rate limit.
+pub async fn run_worker_until_stopped(config: Settings, email_client: EmailClient) -> Result<(), anyhow::Error> {
+	let connection_pool = get_connection_pool(config.database);
+	let email_templates = EmailTemplates::load(&config.application.email_templates_directory)?;
+	worker_loop(
+		connection_pool,
+		email_client,
+		email_templates,
+		config.application.base_url,
+		config.application.hmac_secret,
+	)
+	.await
+}
+
+async fn worker_loop(
+	connection_pool: Pool<Postgres>,
+	email_client: EmailClient,
+	email_templates: EmailTemplates,
+	base_url: String,
+	hmac_secret: Secret<String>,
+) -> Result<(), anyhow::Error> {
+	loop {
+		match try_execute_task(&connection_pool, &email_client, &email_templates, &base_url, &hmac_secret).await {
+			Ok(ExecutionOutcome::EmptyQueue) => {
+				tokio::time::sleep(Duration::from_secs(10)).await;
+			}
+			Err(_) => {
+				tokio::time::sleep(Duration::from_secs(1)).await;
+			}
+			Ok(ExecutionOutcome::Throttled(delay)) => {
+				tokio::time::sleep(delay).await;
+			}
+			Ok(ExecutionOutcome::TaskCompleted) => {}
+		}
+	}
+}
+
+#[tracing::instrument(
+	skip_all,
+	fields(n_tasks=tracing::field::Empty),
+	err
+)]
+pub async fn try_execute_task(
+	connection_pool: &Pool<Postgres>,
+	email_client: &EmailClient,
+	email_templates: &EmailTemplates,
+	base_url: &str,
+	hmac_secret: &Secret<String>,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+	// Leave the queue alone while we are throttled: the tasks stay due and keep their retries.
+	let batch_size = match email_client.send_capacity() {
+		Ok(batch_size) => batch_size,
+		Err(delay) => return Ok(ExecutionOutcome::Throttled(delay)),
+	};
+	let (mut transaction, tasks) = dequeue_tasks(connection_pool, batch_size).await?;
+	if tasks.is_empty() {
+		return Ok(ExecutionOutcome::EmptyQueue);
+	}
+	Span::current().record("n_tasks", tasks.len());
+
+	let subscriber_emails: Vec<_> = tasks.iter().map(|task| task.subscriber_email.clone()).collect();
+	let recipients = get_confirmed_recipients(connection_pool, &subscriber_emails).await?;
+	let mut issues = HashMap::new();
+	let mut completed = Vec::new();
+	let mut deliveries = Vec::new();
+	for task in tasks {
+		let recipient = recipients.get(&task.subscriber_email);
+		if recipient.is_some_and(|recipient| recipient.suppressed) {
+			tracing::info!(
+				subscriber_email = %task.subscriber_email,
+				"Skipping a subscriber whose address is on the suppression list.",
+			);
+			completed.push(task);
+			continue;
+		}
+		let subscriber_id = recipient.map(|recipient| recipient.subscriber_id);
+		match (SubscriberEmail::parse(task.subscriber_email.clone()), subscriber_id) {
+			(_, None) => {
+				tracing::info!(
+					subscriber_email = %task.subscriber_email,
+					"Skipping a subscriber who is no longer confirmed.",
+				);
+				completed.push(task);
+			}
+			(Ok(email), Some(subscriber_id)) => {
+				if let Entry::Vacant(entry) = issues.entry(task.newsletter_issue_id) {
+					entry.insert(get_issue(connection_pool, task.newsletter_issue_id).await?);
+				}
+				let issue = &issues[&task.newsletter_issue_id];
+				let unsubscribe_link = unsubscribe_link(base_url, subscriber_id, hmac_secret);
+				let delivery = personalised_issue(email, task.newsletter_issue_id, issue, &unsubscribe_link, email_templates)?;
+				deliveries.push((task, delivery));
+			}
+			(Err(e), Some(_)) => {
+				tracing::warn!(
+					error.message = %e,
+					subscriber_email = %task.subscriber_email,
+					"Skipping a confirmed subscriber. Their stored contact details are invalid.",
+				);
+				completed.push(task);
+			}
+		}
+	}
+
+	let mut to_retry = Vec::new();
+	if !deliveries.is_empty() {
+		let (tasks, emails): (Vec<_>, Vec<_>) = deliveries.into_iter().unzip();
+		match email_client.send_batch(&emails).await {
+			Ok(outcomes) => {
+				for (task, outcome) in tasks.into_iter().zip(outcomes) {
+					match outcome {
+						DeliveryOutcome::Delivered => completed.push(task),
+						DeliveryOutcome::RetryLater => to_retry.push(task),
+						DeliveryOutcome::FailedPermanently => {
+							tracing::error!(
+								newsletter_issue_id = %task.newsletter_issue_id,
+								subscriber_email = %task.subscriber_email,
+								"The issue was permanently rejected for a confirmed subscriber. Giving up.",
+							);
+							completed.push(task);
+						}
+					}
+				}
+			}
+			// Sending the batch again would deliver the issue twice to every recipient.
+			Err(e) if e.was_accepted() => {
+				tracing::error!(
+					error.cause_chain = ?e,
+					error.message = %e,
+					"The email provider accepted the issue but its answer could not be read. Not resending it.",
+				);
+				completed.extend(tasks);
+			}
+			Err(e) => {
+				tracing::error!(
+					error.cause_chain = ?e,
+					error.message = %e,
+					"Failed to deliver issue to confirmed subscribers. Rescheduling.",
+				);
+				to_retry.extend(tasks);
+			}
+		}
+	}
+
+	for task in &completed {
+		delete_task(&mut transaction, task).await?;
+	}
+	for task in &to_retry {
+		reschedule_task(&mut transaction, task).await?;
+	}
+	transaction.commit().await?;
+	Ok(ExecutionOutcome::TaskCompleted)
+}
+
+struct DeliveryTask {
+	newsletter_issue_id: Uuid,
+	subscriber_email: String,
+	n_retries: i16,
+}
+
+/// The copy of an issue for a single subscriber, with their own unsubscribe link.
+fn personalised_issue(
+	recipient: SubscriberEmail,
+	newsletter_issue_id: Uuid,
+	issue: &NewsletterIssue,
+	unsubscribe_link: &str,
+	email_templates: &EmailTemplates,
+) -> Result<OutgoingEmail, anyhow::Error> {
+	let body = email_templates.render(
+		"newsletter",
+		context! {
+			subject => issue.title,
+			html_content => issue.html_content,
+			text_content => issue.text_content,
+			unsubscribe_link,
+		},
+	)?;
+	Ok(OutgoingEmail::new(recipient, &issue.title, body.html, body.text)
+		.header("List-Unsubscribe", format!("<{}>", unsubscribe_link))
+		.header("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")
+		.tag("newsletter")
+		.metadata("newsletter_issue_id", newsletter_issue_id.to_string()))
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+#[tracing::instrument(skip(connection_pool))]
+async fn dequeue_tasks(
+	connection_pool: &Pool<Postgres>,
+	batch_size: usize,
+) -> Result<(PgTransaction, Vec<DeliveryTask>), anyhow::Error> {
+	let mut transaction = connection_pool.begin().await?;
+	let tasks = sqlx::query_as!(
+		DeliveryTask,
+		r#"
+		SELECT newsletter_issue_id, subscriber_email, n_retries
+		FROM issue_delivery_queue
+		WHERE execute_after <= now()
+		FOR UPDATE
+		SKIP LOCKED
+		LIMIT $1
+		"#,
+		batch_size as i64
+	)
+	.fetch_all(&mut *transaction)
+	.await?;
+	Ok((transaction, tasks))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+	transaction: &mut PgTransaction,
+	task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+	sqlx::query!(
+		r#"
+		DELETE FROM issue_delivery_queue
+		WHERE
+			newsletter_issue_id = $1 AND
+			subscriber_email = $2
+		"#,
+		task.newsletter_issue_id,
+		task.subscriber_email
+	)
+	.execute(&mut **transaction)
+	.await?;
+	Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn reschedule_task(
+	transaction: &mut PgTransaction,
+	task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+	if task.n_retries + 1 >= MAX_DELIVERY_RETRIES {
+		tracing::error!(
+			subscriber_email = %task.subscriber_email,
+			"Giving up on delivering the issue after {} attempts.",
+			task.n_retries + 1
+		);
+		return delete_task(transaction, task).await;
+	}
+	// Back off exponentially: 1, 2, 4, 8... minutes after each failed attempt.
+	let backoff_seconds = 60. * 2_f64.powi(task.n_retries.into());
+	sqlx::query!(
+		r#"
+		UPDATE issue_delivery_queue
+		SET
+			n_retries = n_retries + 1,
+			execute_after = now() + make_interval(secs => $3)
+		WHERE
+			newsletter_issue_id = $1 AND
+			subscriber_email = $2
+		"#,
+		task.newsletter_issue_id,
+		task.subscriber_email,
+		backoff_seconds
+	)
+	.execute(&mut **transaction)
+	.await?;
+	Ok(())
+}
+
+struct Recipient {
+	subscriber_id: Uuid,
+	email: String,
+	suppressed: bool,
+}
+
+/// Looks up which of `subscriber_emails` still belong to confirmed subscribers, in a single
+/// query for the whole batch, keyed by email. Addresses that are not are missing.
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_recipients(
+	connection_pool: &Pool<Postgres>,
+	subscriber_emails: &[String],
+) -> Result<HashMap<String, Recipient>, anyhow::Error> {
+	let recipients = sqlx::query_as!(
+		Recipient,
+		r#"
+		SELECT
+			s.id AS subscriber_id,
+			s.email,
+			EXISTS(SELECT 1 FROM suppressed_addresses WHERE email = lower(s.email)) AS "suppressed!"
+		FROM subscriptions s
+		WHERE s.email = ANY($1) AND s.status = 'confirmed'
+		"#,
+		subscriber_emails
+	)
+	.fetch_all(connection_pool)
+	.await?;
+	Ok(recipients
+		.into_iter()
+		.map(|recipient| (recipient.email.clone(), recipient))
+		.collect())
+}
+
+struct NewsletterIssue {
+	title: String,
+	text_content: String,
+	html_content: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(
+	connection_pool: &Pool<Postgres>,
+	issue_id: Uuid,
+) -> Result<NewsletterIssue, anyhow::Error> {
+	let issue = sqlx::query_as!(
+		NewsletterIssue,
+		r#"
+		SELECT title, text_content, html_content
+		FROM newsletter_issues
+		WHERE newsletter_issue_id = $1
+		"#,
+		issue_id
+	)
+	.fetch_one(connection_pool)
+	.await?;
+	Ok(issue)
+}
@@ -1,6 +1,18 @@
+pub mod authentication;
+pub mod cli;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_templates;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod migrations;
+pub mod outbox;
 pub mod routes;
+pub mod session_state;
+pub mod session_store;
 pub mod startup;
+pub mod suppression;
 pub mod telemetry;
+pub mod unsubscribe;
+pub mod utils;
@@ -1,14 +1,139 @@
-use zero2prod::configuration::get_configuration;
-use zero2prod::startup::Application;
+use std::fmt::{Debug, Display};
+use std::fs::File;
+use std::io::BufReader;
+
+use anyhow::Context;
+use clap::Parser;
+use tokio::task::JoinError;
+use zero2prod::cli::{self, Cli, Command, ConfigCommand, SubscribersCommand, UsersCommand};
+use zero2prod::configuration::{configuration_directory, get_configuration_from, Environment, Settings};
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::outbox::run_dispatcher_until_stopped;
+use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
-	let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
-	init_subscriber(subscriber);
+async fn main() -> anyhow::Result<()> {
+	let cli = Cli::parse();
+	let command = cli.command.unwrap_or(Command::Serve);
+	// Keep stdout clean for the output of the operations commands.
+	match command {
+		Command::Serve => init_subscriber(get_subscriber("zero2prod".into(), "info".into(), std::io::stdout)),
+		// Postgres notices, like those of the migrations' explicit transactions, are noise here.
+		_ => init_subscriber(get_subscriber(
+			"zero2prod".into(),
+			"warn,sqlx::postgres::notice=error".into(),
+			std::io::stderr,
+		)),
+	}
 
-    let config = get_configuration().expect("Failed to read configuration");
-	let app = Application::build(config).await?;
-	app.run_until_stopped().await?;
+	let configuration_directory = match cli.config_dir {
+		Some(directory) => directory,
+		None => configuration_directory()?,
+	};
+	let environment = Environment::from_env()?;
+	let config = get_configuration_from(&configuration_directory, environment)?;
+	match command {
+		Command::Serve => serve(config).await?,
+		Command::Migrate => {
+			cli::migrate(&config.database).await?;
+			eprintln!("The database {} is up to date.", config.database.database_name);
+		}
+		Command::Config(ConfigCommand::Check) => {
+			println!("{}", serde_json::to_string_pretty(&config)?);
+			eprintln!(
+				"The {} configuration in {} is valid.",
+				environment.as_str(),
+				configuration_directory.display()
+			);
+		}
+		Command::Subscribers(command) => {
+			let connection_pool = get_connection_pool(config.database);
+			match command {
+				SubscribersCommand::List => {
+					for subscriber in cli::list_subscribers(&connection_pool).await? {
+						println!(
+							"{:<12} {} {} ({})",
+							subscriber.status,
+							subscriber.subscribed_at.format("%Y-%m-%d %H:%M"),
+							subscriber.email,
+							subscriber.name
+						);
+					}
+				}
+				SubscribersCommand::Export { output: Some(path) } => {
+					let file = File::create(&path).with_context(|| format!("Failed to create {}.", path.display()))?;
+					let n_subscribers = cli::export_subscribers(&connection_pool, file).await?;
+					eprintln!("Exported {} subscribers to {}.", n_subscribers, path.display());
+				}
+				SubscribersCommand::Export { output: None } => {
+					cli::export_subscribers(&connection_pool, std::io::stdout().lock()).await?;
+				}
+				SubscribersCommand::Import { file } => {
+					let reader = File::open(&file).with_context(|| format!("Failed to open {}.", file.display()))?;
+					let report = cli::import_subscribers(&connection_pool, BufReader::new(reader)).await?;
+					for (line, reason) in &report.invalid {
+						eprintln!("Skipped line {}: {}", line, reason);
+					}
+					eprintln!(
+						"Imported {} subscribers. Skipped {} already subscribed, {} suppressed and {} invalid rows.",
+						report.imported,
+						report.already_subscribed,
+						report.suppressed,
+						report.invalid.len()
+					);
+				}
+			}
+		}
+		Command::Users(UsersCommand::CreateAdmin { username, password_file }) => {
+			let password = cli::read_password(password_file.as_deref())?;
+			let connection_pool = get_connection_pool(config.database);
+			cli::create_admin(&connection_pool, &username, password).await?;
+			eprintln!("Created the admin {}.", username);
+		}
+		Command::SendTestEmail { address } => {
+			let email_client = config.email_client.client()?;
+			cli::send_test_email(&email_client, &address).await?;
+			eprintln!("Sent a test email to {}.", address);
+		}
+	}
 	Ok(())
 }
+
+async fn serve(config: Settings) -> anyhow::Result<()> {
+	let app = Application::build(config.clone()).await?;
+	let worker_task = tokio::spawn(run_worker_until_stopped(config.clone(), app.email_client()));
+	let dispatcher_task = tokio::spawn(run_dispatcher_until_stopped(config, app.email_client()));
+	let app_task = tokio::spawn(app.run_until_stopped());
+
+	tokio::select! {
+		outcome = app_task => report_exit("API", outcome),
+		outcome = worker_task => report_exit("Background worker", outcome),
+		outcome = dispatcher_task => report_exit("Outbox dispatcher", outcome),
+	};
+	Ok(())
+}
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+	match outcome {
+		Ok(Ok(())) => {
+			tracing::info!("{} has exited", task_name)
+		}
+		Ok(Err(e)) => {
+			tracing::error!(
+				error.cause_chain = ?e,
+				error.message = %e,
+				"{} failed",
+				task_name
+			)
+		}
+		Err(e) => {
+			tracing::error!(
+				error.cause_chain = ?e,
+				error.message = %e,
+				"{} task failed to complete",
+				task_name
+			)
+		}
+	}
+}
@@ -0,0 +1,87 @@
+//! The database migrations embedded in the binary, applied by `zero2prod migrate` or, with
+//! `application.run_migrations`, when the application starts.
+use sqlx::{migrate::Migrator, Connection, PgConnection};
+
+use crate::{configuration::DatabaseSettings, utils::error_chain_fmt};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Key of the Postgres advisory lock held while migrating, so replicas starting together
+/// take turns: the first one applies the migrations, the others find nothing left to do.
+const MIGRATION_LOCK_KEY: i64 = 0x7a65_726f_3270_726f;
+
+#[derive(thiserror::Error)]
+pub enum MigrationError {
+	#[error("Failed to connect to the database to migrate it.")]
+	Connect(#[source] sqlx::Error),
+	#[error(
+		"The database schema is at version {database_version}, newer than the latest migration \
+		this build knows about ({binary_version}). Deploy a build that includes it."
+	)]
+	SchemaTooNew { database_version: i64, binary_version: i64 },
+	#[error("Failed to apply the database migrations.")]
+	Migrate(#[from] sqlx::migrate::MigrateError),
+	#[error("Failed to inspect the database migrations.")]
+	Unexpected(#[from] sqlx::Error),
+}
+
+impl std::fmt::Debug for MigrationError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		error_chain_fmt(self, f)
+	}
+}
+
+/// Applies the pending migrations, refusing to touch a schema migrated by a newer build.
+///
+/// Uses its own connection rather than a pooled one: if anything fails, closing it
+/// releases the advisory lock.
+#[tracing::instrument(name = "Running database migrations", skip_all, fields(database_name = %config.database_name))]
+pub async fn run_migrations(config: &DatabaseSettings) -> Result<(), MigrationError> {
+	let mut connection = PgConnection::connect_with(&config.with_db())
+		.await
+		.map_err(MigrationError::Connect)?;
+	sqlx::query("SELECT pg_advisory_lock($1)")
+		.bind(MIGRATION_LOCK_KEY)
+		.execute(&mut connection)
+		.await?;
+	check_schema_version(&mut connection).await?;
+	MIGRATOR.run(&mut connection).await?;
+	sqlx::query("SELECT pg_advisory_unlock($1)")
+		.bind(MIGRATION_LOCK_KEY)
+		.execute(&mut connection)
+		.await?;
+	connection.close().await?;
+	Ok(())
+}
+
+/// Fails if a newer build has already migrated the database, leaving the schema as it is.
+#[tracing::instrument(name = "Checking the database schema version", skip_all, fields(database_name = %config.database_name))]
+pub async fn ensure_schema_is_not_newer(config: &DatabaseSettings) -> Result<(), MigrationError> {
+	let mut connection = PgConnection::connect_with(&config.with_db())
+		.await
+		.map_err(MigrationError::Connect)?;
+	check_schema_version(&mut connection).await?;
+	connection.close().await?;
+	Ok(())
+}
+
+async fn check_schema_version(connection: &mut PgConnection) -> Result<(), MigrationError> {
+	let has_history = sqlx::query_scalar!(r#"SELECT to_regclass('_sqlx_migrations') IS NOT NULL AS "exists!""#)
+		.fetch_one(&mut *connection)
+		.await?;
+	if !has_history {
+		return Ok(());
+	}
+	// Not checked at compile time: the table only exists once sqlx has migrated the database.
+	let database_version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations")
+		.fetch_one(&mut *connection)
+		.await?;
+	let binary_version = MIGRATOR.iter().map(|migration| migration.version).max().unwrap_or(0);
+	match database_version {
+		Some(database_version) if database_version > binary_version => Err(MigrationError::SchemaTooNew {
+			database_version,
+			binary_version,
+		}),
+		_ => Ok(()),
+	}
+}
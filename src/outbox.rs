@@ -0,0 +1,208 @@
+//! Transactional outbox for the emails sent on behalf of an HTTP request.
+//!
+//! Request handlers write the message to the `outbox` table in the same transaction as the
+//! state change it announces; a background dispatcher delivers it afterwards, retrying
+//! transient provider failures.
+use std::time::Duration;
+
+use sqlx::{Pool, Postgres, Transaction};
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+use crate::{
+	configuration::Settings,
+	domain::SubscriberEmail,
+	email_client::EmailClient,
+	issue_delivery_worker::ExecutionOutcome,
+	startup::get_connection_pool,
+	suppression::is_suppressed,
+};
+
+const MAX_DISPATCH_ATTEMPTS: i16 = 5;
+
+#[tracing::instrument(
+	name = "Adding an email to the outbox",
+	skip(transaction, recipient, html_content, text_content)
+)]
+pub async fn enqueue_email(
+	transaction: &mut Transaction<'_, Postgres>,
+	recipient: &SubscriberEmail,
+	subject: &str,
+	html_content: &str,
+	text_content: &str,
+) -> Result<Uuid, sqlx::Error> {
+	let message_id = Uuid::new_v4();
+	sqlx::query!(
+		r#"
+		INSERT INTO outbox (id, recipient, subject, html_content, text_content)
+		VALUES ($1, $2, $3, $4, $5)
+		"#,
+		message_id,
+		recipient.as_ref(),
+		subject,
+		html_content,
+		text_content
+	)
+	.execute(&mut **transaction)
+	.await?;
+	Ok(message_id)
+}
+
+/// `email_client` should be shared with the API so both respect the same rate limit.
+pub async fn run_dispatcher_until_stopped(config: Settings, email_client: EmailClient) -> Result<(), anyhow::Error> {
+	let connection_pool = get_connection_pool(config.database);
+	dispatcher_loop(connection_pool, email_client).await
+}
+
+async fn dispatcher_loop(connection_pool: Pool<Postgres>, email_client: EmailClient) -> Result<(), anyhow::Error> {
+	loop {
+		match try_dispatch_email(&connection_pool, &email_client).await {
+			Ok(ExecutionOutcome::EmptyQueue) => {
+				tokio::time::sleep(Duration::from_secs(1)).await;
+			}
+			Ok(ExecutionOutcome::Throttled(delay)) => {
+				tokio::time::sleep(delay).await;
+			}
+			Err(_) => {
+				tokio::time::sleep(Duration::from_secs(1)).await;
+			}
+			Ok(ExecutionOutcome::TaskCompleted) => {}
+		}
+	}
+}
+
+#[tracing::instrument(
+	skip_all,
+	fields(message_id = tracing::field::Empty, recipient = tracing::field::Empty),
+	err
+)]
+pub async fn try_dispatch_email(
+	connection_pool: &Pool<Postgres>,
+	email_client: &EmailClient,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+	if let Err(delay) = email_client.send_capacity() {
+		return Ok(ExecutionOutcome::Throttled(delay));
+	}
+	let Some((mut transaction, message)) = dequeue_message(connection_pool).await? else {
+		return Ok(ExecutionOutcome::EmptyQueue);
+	};
+	Span::current()
+		.record("message_id", display(message.id))
+		.record("recipient", display(&message.recipient));
+
+	if is_suppressed(connection_pool, &message.recipient).await? {
+		tracing::info!("Dropping an email to an address on the suppression list.");
+		mark_failed(&mut transaction, message.id).await?;
+		transaction.commit().await?;
+		return Ok(ExecutionOutcome::TaskCompleted);
+	}
+	let recipient = match SubscriberEmail::parse(message.recipient.clone()) {
+		Ok(recipient) => recipient,
+		Err(e) => {
+			tracing::warn!(error.message = %e, "Dropping an email. Its recipient is invalid.");
+			mark_failed(&mut transaction, message.id).await?;
+			transaction.commit().await?;
+			return Ok(ExecutionOutcome::TaskCompleted);
+		}
+	};
+	match email_client
+		.send_email(&recipient, &message.subject, &message.html_content, &message.text_content)
+		.await
+	{
+		Ok(()) => mark_sent(&mut transaction, message.id).await?,
+		Err(e) if e.is_transient() && message.n_attempts + 1 < MAX_DISPATCH_ATTEMPTS => {
+			tracing::warn!(
+				error.cause_chain = ?e,
+				error.message = %e,
+				"Failed to dispatch an email. Rescheduling.",
+			);
+			reschedule_message(&mut transaction, &message).await?;
+		}
+		Err(e) => {
+			tracing::error!(
+				error.cause_chain = ?e,
+				error.message = %e,
+				n_attempts = message.n_attempts + 1,
+				"Failed to dispatch an email. Giving up.",
+			);
+			mark_failed(&mut transaction, message.id).await?;
+		}
+	}
+	transaction.commit().await?;
+	Ok(ExecutionOutcome::TaskCompleted)
+}
+
+struct OutboxMessage {
+	id: Uuid,
+	recipient: String,
+	subject: String,
+	html_content: String,
+	text_content: String,
+	n_attempts: i16,
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_message(
+	connection_pool: &Pool<Postgres>,
+) -> Result<Option<(PgTransaction, OutboxMessage)>, anyhow::Error> {
+	let mut transaction = connection_pool.begin().await?;
+	let message = sqlx::query_as!(
+		OutboxMessage,
+		r#"
+		SELECT id, recipient, subject, html_content, text_content, n_attempts
+		FROM outbox
+		WHERE sent_at IS NULL AND failed_at IS NULL AND execute_after <= now()
+		ORDER BY execute_after
+		FOR UPDATE
+		SKIP LOCKED
+		LIMIT 1
+		"#,
+	)
+	.fetch_optional(&mut *transaction)
+	.await?;
+	Ok(message.map(|message| (transaction, message)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn mark_sent(transaction: &mut PgTransaction, message_id: Uuid) -> Result<(), anyhow::Error> {
+	sqlx::query!(
+		"UPDATE outbox SET sent_at = now(), n_attempts = n_attempts + 1 WHERE id = $1",
+		message_id
+	)
+	.execute(&mut **transaction)
+	.await?;
+	Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn mark_failed(transaction: &mut PgTransaction, message_id: Uuid) -> Result<(), anyhow::Error> {
+	sqlx::query!(
+		"UPDATE outbox SET failed_at = now(), n_attempts = n_attempts + 1 WHERE id = $1",
+		message_id
+	)
+	.execute(&mut **transaction)
+	.await?;
+	Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn reschedule_message(transaction: &mut PgTransaction, message: &OutboxMessage) -> Result<(), anyhow::Error> {
+	// Back off exponentially: 30 seconds, 1, 2, 4... minutes after each failed attempt.
+	let backoff_seconds = 30. * 2_f64.powi(message.n_attempts.into());
+	sqlx::query!(
+		r#"
+		UPDATE outbox
+		SET
+			n_attempts = n_attempts + 1,
+			execute_after = now() + make_interval(secs => $2)
+		WHERE id = $1
+		"#,
+		message.id,
+		backoff_seconds
+	)
+	.execute(&mut **transaction)
+	.await?;
+	Ok(())
+}
@@ -0,0 +1,62 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use minijinja::HtmlEscape;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{authentication::UserId, utils::e500};
+
+pub async fn admin_dashboard(
+	user_id: web::ReqData<UserId>,
+	connection_pool: web::Data<Pool<Postgres>>,
+) -> Result<HttpResponse, actix_web::Error> {
+	let username = get_username(**user_id, &connection_pool).await.map_err(e500)?;
+	let username = HtmlEscape(&username);
+	Ok(HttpResponse::Ok()
+		.content_type(ContentType::html())
+		.body(format!(
+			r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+	<meta http-equiv="content-type" content="text/html; charset=utf-8">
+	<title>Admin dashboard</title>
+</head>
+<body>
+	<p>Welcome {username}!</p>
+	<p>Available actions:</p>
+	<ol>
+		<li><a href="/admin/newsletters">Publish a newsletter issue</a></li>
+		<li><a href="/admin/suppressions">Manage suppressed addresses</a></li>
+		<li><a href="/admin/password">Change password</a></li>
+		<li>
+			<form name="logoutForm" action="/admin/logout" method="post">
+				<input type="submit" value="Logout">
+			</form>
+		</li>
+	</ol>
+</body>
+</html>"#,
+		)))
+}
+
+#[tracing::instrument(
+	name = "Get username",
+	skip(connection_pool)
+)]
+pub async fn get_username(
+	user_id: Uuid,
+	connection_pool: &Pool<Postgres>,
+) -> Result<String, anyhow::Error> {
+	let row = sqlx::query!(
+		r#"
+		SELECT username
+		FROM users
+		WHERE user_id = $1
+		"#,
+		user_id,
+	)
+	.fetch_one(connection_pool)
+	.await
+	.context("Failed to perform a query to retrieve a username.")?;
+	Ok(row.username)
+}
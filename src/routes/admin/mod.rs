@@ -0,0 +1,11 @@
+mod dashboard;
+mod logout;
+mod newsletters;
+mod password;
+mod suppressions;
+
+pub use dashboard::*;
+pub use logout::*;
+pub use newsletters::*;
+pub use password::*;
+pub use suppressions::*;
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::publish_newsletter_form;
+pub use post::publish_newsletter;
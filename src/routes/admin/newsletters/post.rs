@@ -0,0 +1,108 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use chrono::Utc;
+use sqlx::{Pool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{authentication::UserId, utils::see_other};
+
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+	pub title: String,
+	pub html_content: String,
+	pub text_content: String,
+}
+
+#[tracing::instrument(
+	name = "Publish a newsletter issue",
+	skip(form, connection_pool, user_id),
+	fields(title = %form.title, user_id = %*user_id)
+)]
+pub async fn publish_newsletter(
+	form: web::Form<BodyData>,
+	connection_pool: web::Data<Pool<Postgres>>,
+	user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+	let mut transaction = connection_pool
+		.begin()
+		.await
+		.map_err(|_| actix_web::error::ErrorInternalServerError("Failed to acquire a database connection."))?;
+	let issue_id = insert_newsletter_issue(&mut transaction, &form.title, &form.text_content, &form.html_content)
+		.await
+		.map_err(|_| actix_web::error::ErrorInternalServerError("Failed to store newsletter issue details."))?;
+	enqueue_delivery_tasks(&mut transaction, issue_id)
+		.await
+		.map_err(|_| actix_web::error::ErrorInternalServerError("Failed to enqueue delivery tasks."))?;
+	transaction
+		.commit()
+		.await
+		.map_err(|_| actix_web::error::ErrorInternalServerError("Failed to commit the newsletter issue."))?;
+	FlashMessage::info("The newsletter issue has been accepted - emails will go out shortly.").send();
+	Ok(see_other("/admin/newsletters"))
+}
+
+#[tracing::instrument(
+	name = "Saving the newsletter issue in the database",
+	skip_all
+)]
+async fn insert_newsletter_issue(
+	transaction: &mut Transaction<'_, Postgres>,
+	title: &str,
+	text_content: &str,
+	html_content: &str,
+) -> Result<Uuid, sqlx::Error> {
+	let newsletter_issue_id = Uuid::new_v4();
+	sqlx::query!(
+		r#"
+		INSERT INTO newsletter_issues (
+			newsletter_issue_id,
+			title,
+			text_content,
+			html_content,
+			published_at
+		)
+		VALUES ($1, $2, $3, $4, $5)
+		"#,
+		newsletter_issue_id,
+		title,
+		text_content,
+		html_content,
+		Utc::now()
+	)
+	.execute(&mut **transaction)
+	.await
+	.map_err(|e| {
+		tracing::error!("Failed to execute query: {:?}", e);
+		e
+	})?;
+	Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(
+	name = "Enqueueing a delivery task for every confirmed subscriber",
+	skip(transaction)
+)]
+async fn enqueue_delivery_tasks(
+	transaction: &mut Transaction<'_, Postgres>,
+	newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+	sqlx::query!(
+		r#"
+		INSERT INTO issue_delivery_queue (
+			newsletter_issue_id,
+			subscriber_email
+		)
+		SELECT $1, email
+		FROM subscriptions
+		WHERE status = 'confirmed'
+		"#,
+		newsletter_issue_id,
+	)
+	.execute(&mut **transaction)
+	.await
+	.map_err(|e| {
+		tracing::error!("Failed to execute query: {:?}", e);
+		e
+	})?;
+	Ok(())
+}
@@ -0,0 +1,74 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+	authentication::{
+		self, validate_credentials, AuthError, Credentials, UserId, MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH,
+	},
+	routes::admin::dashboard::get_username,
+	session_state::TypedSession,
+	utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+	current_password: Secret<String>,
+	new_password: Secret<String>,
+	new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+	name = "Change the password of an admin",
+	skip(form, connection_pool, session, user_id),
+	fields(user_id = %*user_id)
+)]
+pub async fn change_password(
+	form: web::Form<FormData>,
+	connection_pool: web::Data<Pool<Postgres>>,
+	session: TypedSession,
+	user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+	let user_id = user_id.into_inner();
+	if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+		FlashMessage::error(
+			"You entered two different new passwords - the field values must match.",
+		)
+		.send();
+		return Ok(see_other("/admin/password"));
+	}
+	let new_password_length = form.new_password.expose_secret().chars().count();
+	if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&new_password_length) {
+		FlashMessage::error(format!(
+			"The new password must be between {} and {} characters long.",
+			MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+		))
+		.send();
+		return Ok(see_other("/admin/password"));
+	}
+
+	let username = get_username(*user_id, &connection_pool).await.map_err(e500)?;
+	let credentials = Credentials {
+		username,
+		password: form.0.current_password,
+	};
+	if let Err(e) = validate_credentials(credentials, &connection_pool).await {
+		return match e {
+			AuthError::InvalidCredentials(_) => {
+				FlashMessage::error("The current password is incorrect.").send();
+				Ok(see_other("/admin/password"))
+			}
+			AuthError::UnexpectedError(_) => Err(e500(e)),
+		};
+	}
+
+	let session_generation = authentication::change_password(*user_id, form.0.new_password, &connection_pool)
+		.await
+		.map_err(e500)?;
+	// Keep the current session alive, every other session of this user is now stale.
+	session.renew();
+	session.insert_session_generation(session_generation).map_err(e500)?;
+	FlashMessage::info("Your password has been changed.").send();
+	Ok(see_other("/admin/password"))
+}
@@ -0,0 +1,81 @@
+use std::fmt::Write;
+
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use minijinja::HtmlEscape;
+use sqlx::{Pool, Postgres};
+
+use crate::{suppression::list_suppressed_addresses, utils::e500};
+
+pub async fn suppressed_addresses_page(
+	flash_messages: IncomingFlashMessages,
+	connection_pool: web::Data<Pool<Postgres>>,
+) -> Result<HttpResponse, actix_web::Error> {
+	let mut msg_html = String::new();
+	for m in flash_messages.iter() {
+		writeln!(msg_html, "<p><i>{}</i></p>", HtmlEscape(m.content())).unwrap();
+	}
+	let mut rows_html = String::new();
+	for address in list_suppressed_addresses(&connection_pool).await.map_err(e500)? {
+		let email = HtmlEscape(&address.email);
+		writeln!(
+			rows_html,
+			r#"		<tr>
+			<td>{email}</td>
+			<td>{reason}</td>
+			<td>{suppressed_at}</td>
+			<td>
+				<form action="/admin/suppressions/remove" method="post">
+					<input type="hidden" name="email" value="{email}">
+					<button type="submit">Remove</button>
+				</form>
+			</td>
+		</tr>"#,
+			reason = HtmlEscape(&address.reason),
+			suppressed_at = address.suppressed_at.to_rfc3339(),
+		)
+		.unwrap();
+	}
+	Ok(HttpResponse::Ok()
+		.content_type(ContentType::html())
+		.body(format!(
+			r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+	<meta http-equiv="content-type" content="text/html; charset=utf-8">
+	<title>Suppressed Addresses</title>
+</head>
+<body>
+	{msg_html}
+	<form action="/admin/suppressions" method="post">
+		<label>Email address:<br>
+			<input
+				type="text"
+				placeholder="Enter the address to suppress"
+				name="email"
+			>
+		</label>
+		<br>
+		<label>Reason:<br>
+			<input
+				type="text"
+				placeholder="Why must this address never be emailed?"
+				name="reason"
+			>
+		</label>
+		<br>
+		<button type="submit">Suppress</button>
+	</form>
+	<table>
+		<tr>
+			<th>Email address</th>
+			<th>Reason</th>
+			<th>Suppressed at</th>
+			<th></th>
+		</tr>
+{rows_html}	</table>
+	<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+		)))
+}
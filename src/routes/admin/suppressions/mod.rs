@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::suppressed_addresses_page;
+pub use post::{add_suppressed_address, remove_suppressed_address};
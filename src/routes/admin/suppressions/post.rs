@@ -0,0 +1,72 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::{Pool, Postgres};
+
+use crate::{
+	authentication::UserId,
+	domain::SubscriberEmail,
+	suppression,
+	utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct AddFormData {
+	email: String,
+	reason: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveFormData {
+	email: String,
+}
+
+#[tracing::instrument(
+	name = "Add an address to the suppression list",
+	skip(form, connection_pool, user_id),
+	fields(email = %form.email, user_id = %*user_id)
+)]
+pub async fn add_suppressed_address(
+	form: web::Form<AddFormData>,
+	connection_pool: web::Data<Pool<Postgres>>,
+	user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+	let AddFormData { email, reason } = form.0;
+	let email = match SubscriberEmail::parse(email) {
+		Ok(email) => email,
+		Err(e) => {
+			FlashMessage::error(e).send();
+			return Ok(see_other("/admin/suppressions"));
+		}
+	};
+	let reason = reason.trim();
+	if reason.is_empty() {
+		FlashMessage::error("You must give a reason for suppressing an address.").send();
+		return Ok(see_other("/admin/suppressions"));
+	}
+	suppression::suppress_address(connection_pool.get_ref(), email.as_ref(), reason)
+		.await
+		.map_err(e500)?;
+	FlashMessage::info(format!("{} will no longer receive any email.", email.as_ref())).send();
+	Ok(see_other("/admin/suppressions"))
+}
+
+#[tracing::instrument(
+	name = "Remove an address from the suppression list",
+	skip(form, connection_pool, user_id),
+	fields(email = %form.email, user_id = %*user_id)
+)]
+pub async fn remove_suppressed_address(
+	form: web::Form<RemoveFormData>,
+	connection_pool: web::Data<Pool<Postgres>>,
+	user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+	if suppression::remove_suppressed_address(&connection_pool, &form.email)
+		.await
+		.map_err(e500)?
+	{
+		FlashMessage::info(format!("{} has been removed from the suppression list.", form.email)).send();
+	} else {
+		FlashMessage::error(format!("{} is not on the suppression list.", form.email)).send();
+	}
+	Ok(see_other("/admin/suppressions"))
+}
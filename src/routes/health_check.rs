@@ -1,5 +1,253 @@
-use actix_web::HttpResponse;
+use std::time::{Duration, Instant};
 
-pub async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().into()
+use actix_web::{http::StatusCode, web, HttpResponse};
+use sqlx::{Pool, Postgres};
+
+use crate::configuration::HealthSettings;
+use crate::email_client::{CircuitState, EmailClient, TransportHealth};
+use crate::startup::ShutdownHandle;
+
+/// Reports the circuit state of every email transport alongside the liveness check.
+pub async fn health_check(email_client: web::Data<EmailClient>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "email_transports": email_client.transport_health(),
+    }))
+}
+
+/// Succeeds as long as the process can serve requests, whatever the state of its dependencies.
+pub async fn liveness() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "up" }))
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Readiness {
+    Ready,
+    NotReady,
+    /// The application is shutting down and wants load balancers to stop routing to it.
+    Draining,
+}
+
+#[derive(serde::Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Up,
+    Down,
+}
+
+#[derive(serde::Serialize)]
+struct ReadinessReport {
+    status: Readiness,
+    checks: Checks,
+}
+
+#[derive(serde::Serialize)]
+struct Checks {
+    database: DatabaseCheck,
+    email: EmailCheck,
+}
+
+#[derive(serde::Serialize)]
+struct DatabaseCheck {
+    status: CheckStatus,
+    latency_ms: u128,
+    pool: PoolUsage,
+}
+
+#[derive(serde::Serialize)]
+struct PoolUsage {
+    size: u32,
+    idle: usize,
+    max_connections: u32,
+}
+
+impl PoolUsage {
+    fn of(connection_pool: &Pool<Postgres>) -> Self {
+        Self {
+            size: connection_pool.size(),
+            idle: connection_pool.num_idle(),
+            max_connections: connection_pool.options().get_max_connections(),
+        }
+    }
+
+    /// Every connection is open and busy: requests are queueing for one.
+    fn is_saturated(&self) -> bool {
+        self.size >= self.max_connections && self.idle == 0
+    }
+}
+
+#[derive(serde::Serialize)]
+struct EmailCheck {
+    status: CheckStatus,
+    transports: Vec<TransportCheck>,
+}
+
+#[derive(serde::Serialize)]
+struct TransportCheck {
+    #[serde(flatten)]
+    health: TransportHealth,
+    /// Only set when the transports are probed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<CheckStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<u128>,
+}
+
+/// Shares the outcome of probing the email transports between readiness requests for
+/// `application.health.email_probe_cache_seconds`: the endpoint is unauthenticated, and
+/// each request would otherwise call the provider's API.
+pub struct EmailProbeCache {
+    ttl: Duration,
+    last_probe: tokio::sync::Mutex<Option<(Instant, Vec<ProbeResult>)>>,
+}
+
+impl EmailProbeCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            last_probe: tokio::sync::Mutex::new(None),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ProbeResult {
+    status: CheckStatus,
+    latency_ms: Option<u128>,
+}
+
+/// Tells load balancers whether to route traffic to this instance.
+///
+/// Only the database gates readiness: emails go through the outbox, so an unreachable
+/// provider delays them without failing requests. Its state is reported all the same.
+///
+/// The body only says what is up or down: why is logged, since anyone can call this.
+#[tracing::instrument(name = "Checking readiness", skip_all)]
+pub async fn readiness(
+    connection_pool: web::Data<Pool<Postgres>>,
+    email_client: web::Data<EmailClient>,
+    settings: web::Data<HealthSettings>,
+    email_probe_cache: web::Data<EmailProbeCache>,
+    shutdown: web::Data<ShutdownHandle>,
+) -> HttpResponse {
+    let timeout = settings.check_timeout();
+    let probe_cache = settings.probe_email_provider.then_some(email_probe_cache.get_ref());
+    let (database, email) = tokio::join!(
+        check_database(&connection_pool, timeout),
+        check_email(&email_client, probe_cache, timeout),
+    );
+    let status = if shutdown.is_draining() {
+        Readiness::Draining
+    } else if database.status == CheckStatus::Down {
+        Readiness::NotReady
+    } else {
+        Readiness::Ready
+    };
+    let status_code = match status {
+        Readiness::Ready => StatusCode::OK,
+        Readiness::NotReady | Readiness::Draining => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    HttpResponse::build(status_code).json(ReadinessReport {
+        status,
+        checks: Checks { database, email },
+    })
+}
+
+async fn check_database(connection_pool: &Pool<Postgres>, timeout: Duration) -> DatabaseCheck {
+    let pool = PoolUsage::of(connection_pool);
+    let start = Instant::now();
+    // Waiting for a connection out of a saturated pool would only tell us what we already know.
+    let outcome = if pool.is_saturated() {
+        Err("The connection pool is saturated.".to_string())
+    } else {
+        match tokio::time::timeout(timeout, sqlx::query!("SELECT 1 AS ping").fetch_one(connection_pool)).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(_) => Err(format!("No answer within {}ms.", timeout.as_millis())),
+        }
+    };
+    if let Err(e) = &outcome {
+        tracing::warn!(error.message = %e, "The database failed its readiness check.");
+    }
+    DatabaseCheck {
+        status: if outcome.is_ok() { CheckStatus::Up } else { CheckStatus::Down },
+        latency_ms: start.elapsed().as_millis(),
+        pool,
+    }
+}
+
+/// Without probing, a transport counts as up unless its circuit is open.
+async fn check_email(email_client: &EmailClient, probe_cache: Option<&EmailProbeCache>, timeout: Duration) -> EmailCheck {
+    let probes = match probe_cache {
+        Some(probe_cache) => Some(probe_email_transports(email_client, probe_cache, timeout).await),
+        None => None,
+    };
+    let transports: Vec<TransportCheck> = email_client
+        .transport_health()
+        .into_iter()
+        .enumerate()
+        .map(|(i, health)| {
+            let probe = probes.as_ref().map(|probes| probes[i]);
+            TransportCheck {
+                health,
+                status: probe.map(|probe| probe.status),
+                latency_ms: probe.and_then(|probe| probe.latency_ms),
+            }
+        })
+        .collect();
+    let any_up = transports.iter().any(|transport| match &transport.status {
+        Some(status) => *status == CheckStatus::Up,
+        None => transport.health.circuit != CircuitState::Open,
+    });
+    EmailCheck {
+        status: if any_up { CheckStatus::Up } else { CheckStatus::Down },
+        transports,
+    }
+}
+
+/// Probes the transports, unless it was done less than the cache's TTL ago. Concurrent
+/// requests wait for the probe in flight rather than starting their own.
+async fn probe_email_transports(
+    email_client: &EmailClient,
+    probe_cache: &EmailProbeCache,
+    timeout: Duration,
+) -> Vec<ProbeResult> {
+    let mut last_probe = probe_cache.last_probe.lock().await;
+    if let Some((probed_at, probes)) = &*last_probe {
+        if probed_at.elapsed() < probe_cache.ttl {
+            return probes.clone();
+        }
+    }
+    let probes: Vec<ProbeResult> = match tokio::time::timeout(timeout, email_client.probe_transports()).await {
+        Ok(probes) => probes
+            .into_iter()
+            .map(|probe| {
+                if let Err(e) = &probe.outcome {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        transport = probe.health.name,
+                        "An email transport failed its readiness probe.",
+                    );
+                }
+                ProbeResult {
+                    status: if probe.outcome.is_ok() { CheckStatus::Up } else { CheckStatus::Down },
+                    latency_ms: Some(probe.latency.as_millis()),
+                }
+            })
+            .collect(),
+        Err(_) => {
+            tracing::warn!("The email transports did not answer their readiness probe within {}ms.", timeout.as_millis());
+            email_client
+                .transport_health()
+                .iter()
+                .map(|_| ProbeResult {
+                    status: CheckStatus::Down,
+                    latency_ms: None,
+                })
+                .collect()
+        }
+    };
+    *last_probe = Some((Instant::now(), probes.clone()));
+    probes
 }
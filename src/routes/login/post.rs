@@ -0,0 +1,72 @@
+use actix_web::{error::InternalError, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::Secret;
+use sqlx::{Pool, Postgres};
+
+use crate::{
+	authentication::{get_session_generation, validate_credentials, AuthError, Credentials},
+	session_state::TypedSession,
+	utils::see_other,
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+	username: String,
+	password: Secret<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LoginError {
+	#[error("Authentication failed")]
+	AuthError(#[source] anyhow::Error),
+	#[error("Something went wrong")]
+	UnexpectedError(#[from] anyhow::Error),
+}
+
+#[tracing::instrument(
+	name = "Log in an admin",
+	skip(form, connection_pool, session),
+	fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn login(
+	form: web::Form<FormData>,
+	connection_pool: web::Data<Pool<Postgres>>,
+	session: TypedSession,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+	let credentials = Credentials {
+		username: form.0.username,
+		password: form.0.password,
+	};
+	tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+	match validate_credentials(credentials, &connection_pool).await {
+		Ok(user_id) => {
+			tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+			let session_generation = get_session_generation(user_id, &connection_pool)
+				.await
+				.map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?
+				.unwrap_or_default();
+			session.renew();
+			session
+				.insert_user_id(user_id)
+				.map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+			session
+				.insert_session_generation(session_generation)
+				.map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+			Ok(see_other("/admin/dashboard"))
+		}
+		Err(e) => {
+			let e = match e {
+				AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+				AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
+			};
+			Err(login_redirect(e))
+		}
+	}
+}
+
+// Redirect to the login page with a generic error message.
+fn login_redirect(e: LoginError) -> InternalError<LoginError> {
+	FlashMessage::error(e.to_string()).send();
+	let response = see_other("/login");
+	InternalError::from_response(e, response)
+}
@@ -1,7 +1,17 @@
+mod admin;
 mod health_check;
+mod login;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_resend;
+mod subscriptions_unsubscribe;
+mod webhooks;
 
+pub use admin::*;
 pub use health_check::*;
+pub use login::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_resend::*;
+pub use subscriptions_unsubscribe::*;
+pub use webhooks::*;
@@ -1,16 +1,14 @@
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use minijinja::context;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use serde::Deserialize;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use sqlx::{query, Pool, Postgres, Transaction};
-use lettre::{
-    address::AddressError, message::{header::ContentType, Mailbox}, transport::smtp::authentication::Credentials, Message, SmtpTransport, Transport
-};
 
-use crate::{domain::{NewSubscriber, SubscriberEmail, SubscriberName}, email_client::EmailClient, startup::ApplicationBaseUrl};
+use crate::{domain::{NewSubscriber, SubscriberEmail, SubscriberName}, email_templates::EmailTemplates, outbox::enqueue_email, startup::{ApplicationBaseUrl, ConfirmationResendCooldown}, suppression::is_suppressed, utils::error_chain_fmt};
 
-#[derive(Debug)]
 pub struct StoreTokenError(sqlx::Error);
 
 impl std::fmt::Display for StoreTokenError {
@@ -19,8 +17,43 @@ impl std::fmt::Display for StoreTokenError {
 	}
 }
 
+impl std::fmt::Debug for StoreTokenError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		error_chain_fmt(self, f)
+	}
+}
+
+impl std::error::Error for StoreTokenError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		Some(&self.0)
+	}
+}
+
 impl ResponseError for StoreTokenError {}
 
+#[derive(thiserror::Error)]
+pub enum SubscribeError {
+	#[error("{0}")]
+	ValidationError(String),
+	#[error(transparent)]
+	UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscribeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		error_chain_fmt(self, f)
+	}
+}
+
+impl ResponseError for SubscribeError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+			SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+		}
+	}
+}
+
 #[derive(Deserialize)]
 pub struct FormData {
     pub email: String,
@@ -29,7 +62,7 @@ pub struct FormData {
 
 #[tracing::instrument(
 	name = "Adding a new subscriber",
-	skip(form, connection_pool, email_client, base_url),
+	skip(form, connection_pool, email_templates, base_url, cooldown),
 	fields(
 		subscriber_email = %form.email,
 		subscriber_name = %form.name
@@ -38,108 +71,85 @@ pub struct FormData {
 pub async fn subscribe(
 	form: web::Form<FormData>,
 	connection_pool: web::Data<Pool<Postgres>>,
-	email_client: web::Data<EmailClient>,
+	email_templates: web::Data<EmailTemplates>,
 	base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, actix_web::Error> {
-	let new_subscriber = match form.0.try_into() {
-		Ok(subscriber) => subscriber,
-		Err(e) => return Err(actix_web::error::ErrorBadRequest(e)),
-	};
-	let mut transaction = match connection_pool.begin().await {
-		Ok(transaction) => transaction,
-		Err(_) => return Err(actix_web::error::ErrorInternalServerError("Failed to acquire a database connection.")),
-	};
-	let subscriber_id = match insert_subscriber(&new_subscriber, &mut transaction).await {
-		Ok(subscriber_id) => subscriber_id,
-		Err(_) => return Err(actix_web::error::ErrorInternalServerError("Failed to save new subscriber details.")),
+	cooldown: web::Data<ConfirmationResendCooldown>,
+) -> Result<HttpResponse, SubscribeError> {
+	let new_subscriber: NewSubscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+	// Same neutral answer as a fresh sign-up, without a confirmation email.
+	if is_suppressed(&connection_pool, new_subscriber.email.as_ref())
+		.await
+		.context("Failed to check the suppression list.")?
+	{
+		return Ok(HttpResponse::Ok().finish());
+	}
+	let mut transaction = connection_pool
+		.begin()
+		.await
+		.context("Failed to acquire a Postgres connection from the pool.")?;
+	let inserted_subscriber_id = insert_subscriber(&new_subscriber, &mut transaction)
+		.await
+		.context("Failed to insert new subscriber in the database.")?;
+	let subscriber_id = match inserted_subscriber_id {
+		Some(subscriber_id) => subscriber_id,
+		// The insert waited for any concurrent sign-up of the same address to commit,
+		// so the existing row is there to lock.
+		None => {
+			let ExistingSubscriber { id, status } = get_existing_subscriber(&new_subscriber.email, &mut transaction)
+				.await
+				.context("Failed to look up existing subscriber details.")?
+				.context("The subscriber conflicting with the new one has disappeared.")?;
+			match status.as_str() {
+				// Answer exactly like a fresh sign-up so the endpoint doesn't reveal who is subscribed.
+				// Bounced and complained addresses must not be emailed again, not even to confirm.
+				"confirmed" | "bounced" | "complained" => return Ok(HttpResponse::Ok().finish()),
+				// Repeated sign-ups share the resend cooldown, or they could flood the address.
+				"pending_confirmation" if is_cooling_down(&mut transaction, id, cooldown.0).await? => {
+					return Ok(HttpResponse::Ok().finish());
+				}
+				"unsubscribed" => restart_double_opt_in(&new_subscriber, id, &mut transaction)
+					.await
+					.context("Failed to move an unsubscribed subscriber back to pending confirmation.")?,
+				_ => {}
+			}
+			delete_tokens(&mut transaction, id)
+				.await
+				.context("Failed to delete the previous subscription tokens.")?;
+			id
+		}
 	};
 	let subscription_token = generate_confirmation_token();
-	store_token(&mut transaction, &subscriber_id, &subscription_token).await?;
-	if transaction.commit().await.is_err() {
-		return HttpResponse::InternalServerError().finish();
-	}
-	if send_confirmation_email(&email_client, new_subscriber, &base_url.0, &subscription_token).await.is_err() {
-		return HttpResponse::InternalServerError().finish();
-	}
-	HttpResponse::Ok().finish()
+	store_token(&mut transaction, &subscriber_id, &subscription_token)
+		.await
+		.context("Failed to store the confirmation token for a new subscriber.")?;
+	enqueue_confirmation_email(&mut transaction, &email_templates, &new_subscriber.email, &base_url.0, &subscription_token)
+		.await
+		.context("Failed to queue a confirmation email.")?;
+	transaction
+		.commit()
+		.await
+		.context("Failed to commit SQL transaction to store a new subscriber.")?;
+	Ok(HttpResponse::Ok().finish())
 }
 
+/// Queues the confirmation email in the outbox; it goes out once `transaction` commits.
 #[tracing::instrument(
-	name = "Send a confirmation email to the new subscriber",
-	skip(email_client, new_subscriber, base_url)
+	name = "Queue a confirmation email for the new subscriber",
+	skip(transaction, email_templates, subscriber_email, base_url, subscription_token)
 )]
-pub async fn send_confirmation_email(
-	email_client: &EmailClient,
-	new_subscriber: NewSubscriber,
+pub async fn enqueue_confirmation_email(
+	transaction: &mut Transaction<'_, Postgres>,
+	email_templates: &EmailTemplates,
+	subscriber_email: &SubscriberEmail,
 	base_url: &str,
 	subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
+	let subject = "Welcome!";
 	let confirmation_link = format!("{}/subscriptions/confirm?subscription_token={}", base_url, subscription_token);
-	let plain_body = &format!(
-		"Welcome to our newsletter!\n\
-		Visit {} to confirm your subscription.",
-		confirmation_link
-	);
-	let html_body = &format!(
-		"Welcome to our newsletter!<br />\
-		Click <a href=\"{}\">here</a> to confirm your subscription.",
-		confirmation_link
-	);
-	email_client
-		.send_email(
-			new_subscriber.email,
-			"Welcome!",
-			html_body,
-			plain_body,
-		)
-		.await
-}
-
-#[tracing::instrument(
-	name = "Sending confirmation email",
-	skip(new_subscriber)
-)]
-fn send_mail(new_subscriber: &NewSubscriber) -> Result<(), String> {
-	let from: Mailbox = "Andre Heber <andre@futureblog.eu>".parse().map_err(|e: AddressError| {
-		tracing::error!("Could not parse email - from: {:?}", e);
-		e.to_string()
-	})?;
-
-	let reply_to: Mailbox = "noreply@futureblog.eu".parse().map_err(|e: AddressError| {
-		tracing::error!("Could not parse email - reply_to: {:?}", e);
-		e.to_string()
-	})?;
-
-	let to: Mailbox = new_subscriber.email.as_ref().parse().map_err(|e: AddressError| {
-		tracing::error!("Could not parse email - to: {:?}", e);
-		e.to_string()
-	})?;
-
-	let email = Message::builder()
-		.from(from)
-		.reply_to(reply_to)
-		.to(to)
-		.subject("Rust Email")
-		.header(ContentType::TEXT_PLAIN)
-		.body(String::from("Hello, this is a test email from Rust!"))
-		.map_err(|e| {
-			tracing::error!("Could not build email: {:?}", e);
-			e.to_string()
-		})?;
-
-	let creds = Credentials::new("andre@futureblog.eu".to_string(), "d6vanPc4RUeQ".to_string());
-	let mailer = SmtpTransport::relay("mail.futureblog.eu")
-    	.map_err(|e| {
-			tracing::error!("Could not connect to server: {:?}", e);
-			e.to_string()
-		})?;
-	let mailer = mailer.credentials(creds).build();
-
-	mailer.send(&email)
-		.map_err(|e| {
-			tracing::error!("Could not send email: {:?}", e);
-			e.to_string()
-		})?;
+	let body = email_templates
+		.render("confirmation", context! { subject, confirmation_link })
+		.context("Failed to render the confirmation email.")?;
+	enqueue_email(transaction, subscriber_email, subject, &body.html, &body.text).await?;
 	Ok(())
 }
 
@@ -154,35 +164,125 @@ impl TryFrom<FormData> for NewSubscriber {
 }
 
 #[tracing::instrument(
-	name = "Saving new subscriber details in the database",
+	name = "Checking whether a confirmation email went out recently",
+	skip(transaction)
+)]
+async fn is_cooling_down(
+	transaction: &mut Transaction<'_, Postgres>,
+	subscriber_id: Uuid,
+	cooldown: std::time::Duration,
+) -> Result<bool, anyhow::Error> {
+	let last_sent_at = get_last_token_created_at(transaction, subscriber_id)
+		.await
+		.context("Failed to look up the latest confirmation token.")?;
+	Ok(last_sent_at.and_then(|last_sent_at| remaining_cooldown(last_sent_at, cooldown)).is_some())
+}
+
+/// How long until another confirmation email may go to the same address, if it may not yet.
+pub fn remaining_cooldown(
+	last_sent_at: DateTime<Utc>,
+	cooldown: std::time::Duration,
+) -> Option<std::time::Duration> {
+	let elapsed = (Utc::now() - last_sent_at).to_std().unwrap_or_default();
+	cooldown.checked_sub(elapsed).filter(|remaining| !remaining.is_zero())
+}
+
+#[tracing::instrument(
+	name = "Retrieve the creation time of the latest subscription token",
+	skip(transaction)
+)]
+pub async fn get_last_token_created_at(
+	transaction: &mut Transaction<'_, Postgres>,
+	subscriber_id: Uuid,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+	sqlx::query_scalar!(
+		"SELECT MAX(created_at) FROM subscription_tokens WHERE subscriber_id = $1",
+		subscriber_id
+	)
+	.fetch_one(&mut **transaction)
+	.await
+	.map_err(|e| {
+		tracing::error!("Failed to execute query: {:?}", e);
+		e
+	})
+}
+
+struct ExistingSubscriber {
+	id: Uuid,
+	status: String,
+}
+
+#[tracing::instrument(
+	name = "Looking up an existing subscriber by email",
+	skip(subscriber_email, transaction)
+)]
+async fn get_existing_subscriber(
+	subscriber_email: &SubscriberEmail,
+	transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+	sqlx::query_as!(
+		ExistingSubscriber,
+		"SELECT id, status FROM subscriptions WHERE email = $1 FOR UPDATE",
+		subscriber_email.as_ref()
+	)
+	.fetch_optional(&mut **transaction)
+	.await
+}
+
+#[tracing::instrument(
+	name = "Moving an unsubscribed subscriber back to pending confirmation",
 	skip(new_subscriber, transaction)
 )]
-async fn insert_subscriber(new_subscriber: &NewSubscriber, transaction: &mut Transaction<'_, Postgres>) -> Result<Uuid, sqlx::Error> {
-	let subscriber_id = Uuid::new_v4();
+async fn restart_double_opt_in(
+	new_subscriber: &NewSubscriber,
+	subscriber_id: Uuid,
+	transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
 	query!(
+		r#"
+		UPDATE subscriptions
+		SET name = $2, status = 'pending_confirmation', subscribed_at = $3, unsubscribed_at = NULL
+		WHERE id = $1
+		"#,
+		subscriber_id,
+		new_subscriber.name.as_ref(),
+		Utc::now()
+	)
+	.execute(&mut **transaction)
+	.await?;
+	Ok(())
+}
+
+/// Returns `None`, leaving the existing row untouched, if the address is already known.
+#[tracing::instrument(
+	name = "Saving new subscriber details in the database",
+	skip(new_subscriber, transaction)
+)]
+async fn insert_subscriber(
+	new_subscriber: &NewSubscriber,
+	transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+	sqlx::query_scalar!(
 		r#"
 		INSERT INTO subscriptions (id, email, name, subscribed_at, status)
 		VALUES ($1, $2, $3, $4, 'pending_confirmation')
+		ON CONFLICT (email) DO NOTHING
+		RETURNING id
 		"#,
-		subscriber_id,
+		Uuid::new_v4(),
 		new_subscriber.email.as_ref(),
 		new_subscriber.name.as_ref(),
 		Utc::now()
 	)
-	.execute(&mut **transaction)
+	.fetch_optional(&mut **transaction)
 	.await
-	.map_err(|e| {
-		tracing::error!("Failed to execute query: {:?}", e);
-		e
-	})?;
-	Ok(subscriber_id)
 }
 
 #[tracing::instrument(
 	name = "Storing subscription token in the database",
 	skip(transaction, subscriber_id, subscription_token)
 )]
-async fn store_token(transaction: &mut Transaction<'_, Postgres>, subscriber_id: &Uuid, subscription_token: &str) -> Result<(), StoreTokenError> {
+pub async fn store_token(transaction: &mut Transaction<'_, Postgres>, subscriber_id: &Uuid, subscription_token: &str) -> Result<(), StoreTokenError> {
 	query!(
 		r#"
 		INSERT INTO subscription_tokens (subscription_token, subscriber_id)
@@ -193,17 +293,51 @@ async fn store_token(transaction: &mut Transaction<'_, Postgres>, subscriber_id:
 	)
 	.execute(&mut **transaction)
 	.await
-	.map_err(|e| {
-		tracing::error!("Failed to execute query: {:?}", e);
-		StoreTokenError(e)
-	})?;
+	.map_err(StoreTokenError)?;
+	Ok(())
+}
+
+#[tracing::instrument(
+	name = "Deleting the subscription tokens of a subscriber",
+	skip(transaction)
+)]
+pub async fn delete_tokens(
+	transaction: &mut Transaction<'_, Postgres>,
+	subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+	query!(
+		"DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+		subscriber_id
+	)
+	.execute(&mut **transaction)
+	.await?;
 	Ok(())
 }
 
-fn generate_confirmation_token() -> String {
+pub fn generate_confirmation_token() -> String {
 	let mut rng = thread_rng();
 	std::iter::repeat_with(|| rng.sample(Alphanumeric))
 		.map(char::from)
 		.take(25)
 		.collect()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::Utc;
+	use claim::{assert_none, assert_some};
+
+	use super::remaining_cooldown;
+
+	#[test]
+	fn a_recent_token_is_still_cooling_down() {
+		let last_sent_at = Utc::now() - chrono::Duration::seconds(10);
+		assert_some!(remaining_cooldown(last_sent_at, std::time::Duration::from_secs(300)));
+	}
+
+	#[test]
+	fn an_old_token_is_no_longer_cooling_down() {
+		let last_sent_at = Utc::now() - chrono::Duration::seconds(301);
+		assert_none!(remaining_cooldown(last_sent_at, std::time::Duration::from_secs(300)));
+	}
+}
@@ -1,7 +1,14 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{
+	http::{header::ContentType, StatusCode},
+	web, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
 use sqlx::{pool::Pool, Postgres};
 use uuid::Uuid;
 
+use crate::{startup::ConfirmationTokenTtl, utils::error_chain_fmt};
+
 #[derive(serde::Deserialize)]
 pub struct Parameters {
 	pub subscription_token: String,
@@ -9,46 +16,176 @@ pub struct Parameters {
 
 #[tracing::instrument(
 	name = "Confirm a pending subscriber",
-	skip(parameters),
+	skip(parameters, pool, token_ttl),
 )]
 pub async fn confirm(
 	parameters: web::Query<Parameters>,
 	pool: web::Data<Pool<Postgres>>,
-) -> HttpResponse {
-	let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-		Ok(id) => id,
-		Err(_) => return HttpResponse::BadRequest().finish(),
-	};
-	match id {
-		None => HttpResponse::Unauthorized().finish(),
-		Some(subscriber_id) => {
-			if confirm_subscriber(&pool, subscriber_id).await.is_err() {
-				return HttpResponse::InternalServerError().finish();
-			}
-			HttpResponse::Ok().finish()
+	token_ttl: web::Data<ConfirmationTokenTtl>,
+) -> Result<HttpResponse, ConfirmationError> {
+	let token = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+		.await
+		.context("Failed to retrieve the subscriber id associated with the provided token.")?
+		.ok_or(ConfirmationError::UnknownToken)?;
+	if token.used_at.is_some() {
+		return Ok(already_confirmed_page());
+	}
+	if token.is_expired(token_ttl.0) {
+		return Err(ConfirmationError::ExpiredToken);
+	}
+	let outcome = confirm_subscriber(&pool, &parameters.subscription_token, token.subscriber_id)
+		.await
+		.context("Failed to update the subscriber status to `confirmed`.")?;
+	match outcome {
+		ConfirmationOutcome::Confirmed => Ok(confirmed_page()),
+		ConfirmationOutcome::AlreadyConfirmed => Ok(already_confirmed_page()),
+		ConfirmationOutcome::NoLongerPending => Ok(cannot_be_confirmed_page()),
+	}
+}
+
+#[derive(thiserror::Error)]
+pub enum ConfirmationError {
+	#[error("There is no subscriber associated with the provided token.")]
+	UnknownToken,
+	#[error("This confirmation link has expired. Please request a new one.")]
+	ExpiredToken,
+	#[error(transparent)]
+	UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConfirmationError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		error_chain_fmt(self, f)
+	}
+}
+
+impl ResponseError for ConfirmationError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			ConfirmationError::UnknownToken => StatusCode::UNAUTHORIZED,
+			ConfirmationError::ExpiredToken => StatusCode::GONE,
+			ConfirmationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
 		}
 	}
 }
 
+fn confirmed_page() -> HttpResponse {
+	HttpResponse::Ok()
+		.content_type(ContentType::html())
+		.body(
+			r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+	<meta http-equiv="content-type" content="text/html; charset=utf-8">
+	<title>Subscription confirmed</title>
+</head>
+<body>
+	<p>Thanks! Your subscription is confirmed.</p>
+</body>
+</html>"#,
+		)
+}
+
+fn already_confirmed_page() -> HttpResponse {
+	HttpResponse::Ok()
+		.content_type(ContentType::html())
+		.body(
+			r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+	<meta http-equiv="content-type" content="text/html; charset=utf-8">
+	<title>Already confirmed</title>
+</head>
+<body>
+	<p>This link has already been used - your subscription is already confirmed.</p>
+</body>
+</html>"#,
+		)
+}
+
+fn cannot_be_confirmed_page() -> HttpResponse {
+	HttpResponse::Ok()
+		.content_type(ContentType::html())
+		.body(
+			r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+	<meta http-equiv="content-type" content="text/html; charset=utf-8">
+	<title>Subscription not confirmed</title>
+</head>
+<body>
+	<p>This subscription can no longer be confirmed. Please subscribe again.</p>
+</body>
+</html>"#,
+		)
+}
+
+pub struct SubscriptionToken {
+	pub subscriber_id: Uuid,
+	pub created_at: DateTime<Utc>,
+	pub used_at: Option<DateTime<Utc>>,
+}
+
+impl SubscriptionToken {
+	fn is_expired(&self, ttl: std::time::Duration) -> bool {
+		match chrono::Duration::from_std(ttl) {
+			Ok(ttl) => self.created_at + ttl < Utc::now(),
+			Err(_) => false,
+		}
+	}
+}
+
+pub enum ConfirmationOutcome {
+	Confirmed,
+	AlreadyConfirmed,
+	/// The subscriber has unsubscribed, bounced or complained since the token was issued.
+	NoLongerPending,
+}
+
+/// Consumes `subscription_token` and confirms its subscriber in one transaction.
+///
+/// Only a `pending_confirmation` subscriber is moved to `confirmed`, so a token
+/// cannot bring back someone who has since unsubscribed. The token is then left unused.
 #[tracing::instrument(
 	name = "Mark a subscriber as confirmed in the database",
-	skip(pool, subscriber_id),
+	skip(pool, subscription_token, subscriber_id),
 )]
 pub async fn confirm_subscriber(
 	pool: &Pool<Postgres>,
+	subscription_token: &str,
 	subscriber_id: Uuid,
-) -> Result<(), sqlx::Error> {
-	sqlx::query!(
-		"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1",
+) -> Result<ConfirmationOutcome, sqlx::Error> {
+	let mut transaction = pool.begin().await?;
+	let n_consumed = sqlx::query!(
+		r#"
+		UPDATE subscription_tokens SET used_at = now()
+		WHERE subscription_token = $1 AND used_at IS NULL
+		"#,
+		subscription_token
+	)
+	.execute(&mut *transaction)
+	.await?
+	.rows_affected();
+	if n_consumed == 0 {
+		// A concurrent click consumed the token first.
+		return Ok(ConfirmationOutcome::AlreadyConfirmed);
+	}
+	let n_confirmed = sqlx::query!(
+		r#"
+		UPDATE subscriptions SET status = 'confirmed'
+		WHERE id = $1 AND status = 'pending_confirmation'
+		"#,
 		subscriber_id
 	)
-	.execute(pool)
-	.await
-	.map_err(|e| {
-		tracing::error!("Failed to execute query: {:?}", e);
-		e
-	})?;
-	Ok(())
+	.execute(&mut *transaction)
+	.await?
+	.rows_affected();
+	if n_confirmed == 0 {
+		transaction.rollback().await?;
+		return Ok(ConfirmationOutcome::NoLongerPending);
+	}
+	transaction.commit().await?;
+	Ok(ConfirmationOutcome::Confirmed)
 }
 
 #[tracing::instrument(
@@ -58,16 +195,12 @@ pub async fn confirm_subscriber(
 pub async fn get_subscriber_id_from_token(
 	pool: &Pool<Postgres>,
 	subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-	let result = sqlx::query!(
-		"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1",
+) -> Result<Option<SubscriptionToken>, sqlx::Error> {
+	sqlx::query_as!(
+		SubscriptionToken,
+		"SELECT subscriber_id, created_at, used_at FROM subscription_tokens WHERE subscription_token = $1",
 		subscription_token
 	)
 	.fetch_optional(pool)
 	.await
-	.map_err(|e| {
-		tracing::error!("Failed to execute query: {:?}", e);
-		e
-	})?;
-	Ok(result.map(|r| r.subscriber_id))
 }
\ No newline at end of file
@@ -0,0 +1,91 @@
+use actix_web::{web, HttpResponse};
+use sqlx::{Pool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+	domain::SubscriberEmail,
+	email_templates::EmailTemplates,
+	routes::{
+		delete_tokens, enqueue_confirmation_email, generate_confirmation_token, get_last_token_created_at,
+		remaining_cooldown, store_token,
+	},
+	startup::{ApplicationBaseUrl, ConfirmationResendCooldown},
+	suppression::is_suppressed,
+	utils::{e400, e500},
+};
+
+#[derive(serde::Deserialize)]
+pub struct ResendFormData {
+	pub email: String,
+}
+
+/// Issues a fresh confirmation token for a pending subscriber and sends it again.
+///
+/// Unknown, suppressed and already confirmed addresses, as well as pending ones still within
+/// the cooldown, get the same 200 as a successful resend, so the endpoint cannot be used to
+/// probe who is on the list.
+#[tracing::instrument(
+	name = "Resend a confirmation email",
+	skip(form, connection_pool, email_templates, base_url, cooldown),
+	fields(subscriber_email = %form.email)
+)]
+pub async fn resend_confirmation(
+	form: web::Form<ResendFormData>,
+	connection_pool: web::Data<Pool<Postgres>>,
+	email_templates: web::Data<EmailTemplates>,
+	base_url: web::Data<ApplicationBaseUrl>,
+	cooldown: web::Data<ConfirmationResendCooldown>,
+) -> Result<HttpResponse, actix_web::Error> {
+	let subscriber_email = SubscriberEmail::parse(form.0.email).map_err(e400)?;
+	if is_suppressed(&connection_pool, subscriber_email.as_ref()).await.map_err(e500)? {
+		return Ok(HttpResponse::Ok().finish());
+	}
+	let mut transaction = connection_pool.begin().await.map_err(e500)?;
+	let subscriber_id = match get_pending_subscriber_id(&mut transaction, &subscriber_email)
+		.await
+		.map_err(e500)?
+	{
+		Some(subscriber_id) => subscriber_id,
+		None => return Ok(HttpResponse::Ok().finish()),
+	};
+	if let Some(last_sent_at) = get_last_token_created_at(&mut transaction, subscriber_id)
+		.await
+		.map_err(e500)?
+	{
+		if remaining_cooldown(last_sent_at, cooldown.0).is_some() {
+			return Ok(HttpResponse::Ok().finish());
+		}
+	}
+	delete_tokens(&mut transaction, subscriber_id).await.map_err(e500)?;
+	let subscription_token = generate_confirmation_token();
+	store_token(&mut transaction, &subscriber_id, &subscription_token).await?;
+	enqueue_confirmation_email(&mut transaction, &email_templates, &subscriber_email, &base_url.0, &subscription_token)
+		.await
+		.map_err(e500)?;
+	transaction.commit().await.map_err(e500)?;
+	Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(
+	name = "Lock a pending subscriber by email",
+	skip(transaction, subscriber_email)
+)]
+async fn get_pending_subscriber_id(
+	transaction: &mut Transaction<'_, Postgres>,
+	subscriber_email: &SubscriberEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+	sqlx::query_scalar!(
+		r#"
+		SELECT id FROM subscriptions
+		WHERE email = $1 AND status = 'pending_confirmation'
+		FOR UPDATE
+		"#,
+		subscriber_email.as_ref()
+	)
+	.fetch_optional(&mut **transaction)
+	.await
+	.map_err(|e| {
+		tracing::error!("Failed to execute query: {:?}", e);
+		e
+	})
+}
@@ -0,0 +1,104 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{startup::HmacSecret, unsubscribe::verify_unsubscribe_token};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+	pub subscriber_id: Uuid,
+	pub token: String,
+}
+
+#[tracing::instrument(
+	name = "Show the unsubscribe confirmation page",
+	skip(parameters, hmac_secret),
+	fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn unsubscribe_form(
+	parameters: web::Query<UnsubscribeParameters>,
+	hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+	if !verify_unsubscribe_token(parameters.subscriber_id, &parameters.token, &hmac_secret.0) {
+		return HttpResponse::Unauthorized().finish();
+	}
+	HttpResponse::Ok()
+		.content_type(ContentType::html())
+		.body(format!(
+			r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+	<meta http-equiv="content-type" content="text/html; charset=utf-8">
+	<title>Unsubscribe</title>
+</head>
+<body>
+	<p>Do you really want to stop receiving our newsletter?</p>
+	<form action="/subscriptions/unsubscribe?subscriber_id={}&token={}" method="post">
+		<button type="submit">Unsubscribe</button>
+	</form>
+</body>
+</html>"#,
+			parameters.subscriber_id, parameters.token,
+		))
+}
+
+/// Handles both the confirmation form and RFC 8058 one-click requests, which
+/// POST `List-Unsubscribe=One-Click` to the link in the `List-Unsubscribe` header.
+#[tracing::instrument(
+	name = "Unsubscribe a subscriber",
+	skip(parameters, connection_pool, hmac_secret),
+	fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn unsubscribe(
+	parameters: web::Query<UnsubscribeParameters>,
+	connection_pool: web::Data<Pool<Postgres>>,
+	hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+	if !verify_unsubscribe_token(parameters.subscriber_id, &parameters.token, &hmac_secret.0) {
+		return HttpResponse::Unauthorized().finish();
+	}
+	if mark_subscriber_as_unsubscribed(&connection_pool, parameters.subscriber_id).await.is_err() {
+		return HttpResponse::InternalServerError().finish();
+	}
+	HttpResponse::Ok()
+		.content_type(ContentType::html())
+		.body(
+			r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+	<meta http-equiv="content-type" content="text/html; charset=utf-8">
+	<title>Unsubscribed</title>
+</head>
+<body>
+	<p>You have been unsubscribed. You won't receive any further issues.</p>
+</body>
+</html>"#,
+		)
+}
+
+#[tracing::instrument(
+	name = "Mark a subscriber as unsubscribed in the database",
+	skip(connection_pool)
+)]
+pub async fn mark_subscriber_as_unsubscribed(
+	connection_pool: &Pool<Postgres>,
+	subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+	sqlx::query!(
+		r#"
+		UPDATE subscriptions
+		SET status = 'unsubscribed', unsubscribed_at = $2
+		WHERE id = $1 AND status IN ('pending_confirmation', 'confirmed')
+		"#,
+		subscriber_id,
+		Utc::now()
+	)
+	.execute(connection_pool)
+	.await
+	.map_err(|e| {
+		tracing::error!("Failed to execute query: {:?}", e);
+		e
+	})?;
+	Ok(())
+}
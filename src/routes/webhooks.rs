@@ -0,0 +1,281 @@
+use actix_web::{
+	http::{
+		header::{self, HeaderMap, HeaderValue},
+		StatusCode,
+	},
+	web, HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Pool, Postgres, Transaction};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::{
+	authentication::Credentials, configuration::PostmarkWebhookSettings, suppression::suppress_address,
+	utils::error_chain_fmt,
+};
+
+/// Bounce types after which Postmark will never be able to deliver to the address again.
+const PERMANENT_BOUNCE_TYPES: [&str; 2] = ["HardBounce", "BadEmailAddress"];
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+	#[error("Authentication failed.")]
+	AuthError(#[source] anyhow::Error),
+	#[error("The webhook payload could not be parsed.")]
+	InvalidPayload(#[source] serde_json::Error),
+	#[error(transparent)]
+	UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		error_chain_fmt(self, f)
+	}
+}
+
+impl ResponseError for WebhookError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			WebhookError::AuthError(_) => StatusCode::UNAUTHORIZED,
+			WebhookError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+			WebhookError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+		}
+	}
+
+	fn error_response(&self) -> HttpResponse {
+		let mut response = HttpResponse::new(self.status_code());
+		if let WebhookError::AuthError(_) = self {
+			let header_value = HeaderValue::from_static(r#"Basic realm="postmark""#);
+			response.headers_mut().insert(header::WWW_AUTHENTICATE, header_value);
+		}
+		response
+	}
+}
+
+/// The subset of Postmark's webhook payloads we act upon.
+/// See <https://postmarkapp.com/developer/webhooks/webhooks-overview>.
+#[derive(serde::Deserialize)]
+#[serde(tag = "RecordType")]
+enum PostmarkEvent {
+	Bounce {
+		#[serde(rename = "Type")]
+		bounce_type: String,
+		#[serde(rename = "Email")]
+		email: String,
+		#[serde(rename = "MessageID")]
+		message_id: Option<String>,
+		#[serde(rename = "BouncedAt")]
+		bounced_at: Option<String>,
+	},
+	SpamComplaint {
+		#[serde(rename = "Email")]
+		email: String,
+		#[serde(rename = "MessageID")]
+		message_id: Option<String>,
+		#[serde(rename = "BouncedAt")]
+		bounced_at: Option<String>,
+	},
+	Delivery {
+		#[serde(rename = "Recipient")]
+		recipient: String,
+		#[serde(rename = "MessageID")]
+		message_id: Option<String>,
+		#[serde(rename = "DeliveredAt")]
+		delivered_at: Option<String>,
+	},
+	/// Opens, clicks and the other record types Postmark can be configured to send.
+	#[serde(other)]
+	Other,
+}
+
+struct EmailEvent {
+	record_type: &'static str,
+	email: String,
+	message_id: Option<String>,
+	occurred_at: Option<DateTime<Utc>>,
+	/// The status the matching subscriber moves to, if the event makes the address unusable.
+	subscriber_status: Option<&'static str>,
+	suppression_reason: Option<&'static str>,
+}
+
+impl PostmarkEvent {
+	fn into_email_event(self) -> Option<EmailEvent> {
+		let event = match self {
+			PostmarkEvent::Bounce { bounce_type, email, message_id, bounced_at } => {
+				let is_permanent = PERMANENT_BOUNCE_TYPES.contains(&bounce_type.as_str());
+				EmailEvent {
+					record_type: "bounce",
+					email,
+					message_id,
+					occurred_at: parse_timestamp(bounced_at),
+					subscriber_status: is_permanent.then_some("bounced"),
+					suppression_reason: is_permanent.then_some("Hard bounce reported by Postmark"),
+				}
+			}
+			PostmarkEvent::SpamComplaint { email, message_id, bounced_at } => EmailEvent {
+				record_type: "spam_complaint",
+				email,
+				message_id,
+				occurred_at: parse_timestamp(bounced_at),
+				subscriber_status: Some("complained"),
+				suppression_reason: Some("Spam complaint reported by Postmark"),
+			},
+			PostmarkEvent::Delivery { recipient, message_id, delivered_at } => EmailEvent {
+				record_type: "delivery",
+				email: recipient,
+				message_id,
+				occurred_at: parse_timestamp(delivered_at),
+				subscriber_status: None,
+				suppression_reason: None,
+			},
+			PostmarkEvent::Other => return None,
+		};
+		Some(event)
+	}
+}
+
+fn parse_timestamp(timestamp: Option<String>) -> Option<DateTime<Utc>> {
+	DateTime::parse_from_rfc3339(&timestamp?)
+		.ok()
+		.map(|t| t.with_timezone(&Utc))
+}
+
+#[tracing::instrument(
+	name = "Processing a Postmark webhook",
+	skip(request, body, pool, webhook_settings),
+	fields(record_type = tracing::field::Empty, recipient = tracing::field::Empty)
+)]
+pub async fn postmark_webhook(
+	request: HttpRequest,
+	body: web::Bytes,
+	pool: web::Data<Pool<Postgres>>,
+	webhook_settings: web::Data<PostmarkWebhookSettings>,
+) -> Result<HttpResponse, WebhookError> {
+	let credentials = basic_authentication(request.headers()).map_err(WebhookError::AuthError)?;
+	validate_webhook_credentials(&credentials, &webhook_settings).map_err(WebhookError::AuthError)?;
+
+	let payload: serde_json::Value = serde_json::from_slice(&body).map_err(WebhookError::InvalidPayload)?;
+	let event: PostmarkEvent = serde_json::from_value(payload.clone()).map_err(WebhookError::InvalidPayload)?;
+	// Postmark retries anything but a 200, so acknowledge the record types we don't track.
+	let Some(event) = event.into_email_event() else {
+		return Ok(HttpResponse::Ok().finish());
+	};
+	tracing::Span::current()
+		.record("record_type", event.record_type)
+		.record("recipient", tracing::field::display(&event.email));
+
+	let mut transaction = pool
+		.begin()
+		.await
+		.context("Failed to acquire a Postgres connection from the pool.")?;
+	store_email_event(&mut transaction, &event, &payload)
+		.await
+		.context("Failed to store the email event.")?;
+	if let Some(status) = event.subscriber_status {
+		update_subscriber_status(&mut transaction, &event.email, status)
+			.await
+			.context("Failed to update the status of the subscriber.")?;
+	}
+	if let Some(reason) = event.suppression_reason {
+		suppress_address(&mut *transaction, &event.email, reason)
+			.await
+			.context("Failed to add the address to the suppression list.")?;
+	}
+	transaction
+		.commit()
+		.await
+		.context("Failed to commit SQL transaction to store an email event.")?;
+	Ok(HttpResponse::Ok().finish())
+}
+
+fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+	let header_value = headers
+		.get(header::AUTHORIZATION)
+		.context("The 'Authorization' header was missing.")?
+		.to_str()
+		.context("The 'Authorization' header was not a valid UTF8 string.")?;
+	let base64encoded_segment = header_value
+		.strip_prefix("Basic ")
+		.context("The authorization scheme was not 'Basic'.")?;
+	let decoded_bytes = base64::engine::general_purpose::STANDARD
+		.decode(base64encoded_segment)
+		.context("Failed to base64-decode 'Basic' credentials.")?;
+	let decoded_credentials = String::from_utf8(decoded_bytes)
+		.context("The decoded credential string is not valid UTF8.")?;
+	let (username, password) = decoded_credentials
+		.split_once(':')
+		.context("A password must be provided in 'Basic' auth.")?;
+	Ok(Credentials {
+		username: username.to_owned(),
+		password: Secret::new(password.to_owned()),
+	})
+}
+
+fn validate_webhook_credentials(
+	credentials: &Credentials,
+	webhook_settings: &PostmarkWebhookSettings,
+) -> Result<(), anyhow::Error> {
+	let username_matches = credentials
+		.username
+		.as_bytes()
+		.ct_eq(webhook_settings.username.as_bytes());
+	let password_matches = credentials
+		.password
+		.expose_secret()
+		.as_bytes()
+		.ct_eq(webhook_settings.password.expose_secret().as_bytes());
+	if bool::from(username_matches & password_matches) {
+		Ok(())
+	} else {
+		Err(anyhow::anyhow!("Invalid username or password."))
+	}
+}
+
+#[tracing::instrument(name = "Storing an email event", skip(transaction, event, payload))]
+async fn store_email_event(
+	transaction: &mut Transaction<'_, Postgres>,
+	event: &EmailEvent,
+	payload: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+	sqlx::query!(
+		r#"
+		INSERT INTO email_events (id, record_type, email, message_id, payload, occurred_at, received_at)
+		VALUES ($1, $2, $3, $4, $5, $6, now())
+		"#,
+		Uuid::new_v4(),
+		event.record_type,
+		event.email,
+		event.message_id,
+		payload,
+		event.occurred_at
+	)
+	.execute(&mut **transaction)
+	.await?;
+	Ok(())
+}
+
+/// A spam complaint takes precedence over a bounce, since it is the stronger signal
+/// that we must never email the address again.
+#[tracing::instrument(name = "Excluding a subscriber from future sends", skip(transaction, email))]
+async fn update_subscriber_status(
+	transaction: &mut Transaction<'_, Postgres>,
+	email: &str,
+	status: &str,
+) -> Result<(), sqlx::Error> {
+	sqlx::query!(
+		r#"
+		UPDATE subscriptions
+		SET status = $2
+		WHERE email = $1 AND status <> 'complained'
+		"#,
+		email,
+		status
+	)
+	.execute(&mut **transaction)
+	.await?;
+	Ok(())
+}
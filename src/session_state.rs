@@ -0,0 +1,45 @@
+use std::future::{ready, Ready};
+
+use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use uuid::Uuid;
+
+pub struct TypedSession(Session);
+
+impl TypedSession {
+	const USER_ID_KEY: &'static str = "user_id";
+	const SESSION_GENERATION_KEY: &'static str = "session_generation";
+
+	pub fn renew(&self) {
+		self.0.renew();
+	}
+
+	pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+		self.0.insert(Self::USER_ID_KEY, user_id)
+	}
+
+	pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+		self.0.get(Self::USER_ID_KEY)
+	}
+
+	pub fn insert_session_generation(&self, session_generation: i32) -> Result<(), SessionInsertError> {
+		self.0.insert(Self::SESSION_GENERATION_KEY, session_generation)
+	}
+
+	pub fn get_session_generation(&self) -> Result<Option<i32>, SessionGetError> {
+		self.0.get(Self::SESSION_GENERATION_KEY)
+	}
+
+	pub fn log_out(self) {
+		self.0.purge()
+	}
+}
+
+impl FromRequest for TypedSession {
+	type Error = <Session as FromRequest>::Error;
+	type Future = Ready<Result<TypedSession, Self::Error>>;
+
+	fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+		ready(Ok(TypedSession(req.get_session())))
+	}
+}
@@ -0,0 +1,131 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::Instant,
+};
+
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+
+use super::{generate_session_key, SessionState};
+
+/// Keeps session state in the memory of the current process.
+///
+/// Sessions are lost on restart and are not shared between replicas: only
+/// meant for local development and single-instance deployments.
+#[derive(Clone, Default)]
+pub struct MemorySessionStore {
+	sessions: Arc<Mutex<HashMap<String, (SessionState, Instant)>>>,
+}
+
+impl MemorySessionStore {
+	fn expires_at(ttl: &Duration) -> Instant {
+		Instant::now() + ttl.unsigned_abs()
+	}
+
+	fn insert(&self, session_state: SessionState, ttl: &Duration) -> SessionKey {
+		let session_key = generate_session_key();
+		let mut sessions = self.sessions.lock().unwrap();
+		let now = Instant::now();
+		sessions.retain(|_, (_, expires_at)| *expires_at > now);
+		sessions.insert(
+			session_key.as_ref().to_owned(),
+			(session_state, Self::expires_at(ttl)),
+		);
+		session_key
+	}
+}
+
+impl SessionStore for MemorySessionStore {
+	async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+		let mut sessions = self.sessions.lock().unwrap();
+		match sessions.get(session_key.as_ref()) {
+			Some((_, expires_at)) if *expires_at <= Instant::now() => {
+				sessions.remove(session_key.as_ref());
+				Ok(None)
+			}
+			Some((state, _)) => Ok(Some(state.clone())),
+			None => Ok(None),
+		}
+	}
+
+	async fn save(&self, session_state: SessionState, ttl: &Duration) -> Result<SessionKey, SaveError> {
+		Ok(self.insert(session_state, ttl))
+	}
+
+	async fn update(
+		&self,
+		session_key: SessionKey,
+		session_state: SessionState,
+		ttl: &Duration,
+	) -> Result<SessionKey, UpdateError> {
+		if let Some(session) = self.sessions.lock().unwrap().get_mut(session_key.as_ref()) {
+			*session = (session_state, Self::expires_at(ttl));
+			return Ok(session_key);
+		}
+		Ok(self.insert(session_state, ttl))
+	}
+
+	async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> Result<(), anyhow::Error> {
+		if let Some((_, expires_at)) = self.sessions.lock().unwrap().get_mut(session_key.as_ref()) {
+			*expires_at = Self::expires_at(ttl);
+		}
+		Ok(())
+	}
+
+	async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+		self.sessions.lock().unwrap().remove(session_key.as_ref());
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use actix_session::storage::SessionStore;
+	use actix_web::cookie::time::Duration;
+	use claim::{assert_none, assert_some};
+
+	use super::MemorySessionStore;
+
+	fn state() -> HashMap<String, String> {
+		HashMap::from([("user_id".to_string(), "\"42\"".to_string())])
+	}
+
+	#[tokio::test]
+	async fn a_saved_session_can_be_loaded() {
+		let store = MemorySessionStore::default();
+		let session_key = store.save(state(), &Duration::minutes(5)).await.unwrap();
+
+		let loaded = store.load(&session_key).await.unwrap();
+
+		assert_eq!(assert_some!(loaded), state());
+	}
+
+	#[tokio::test]
+	async fn an_expired_session_is_not_loaded() {
+		let store = MemorySessionStore::default();
+		let session_key = store.save(state(), &Duration::ZERO).await.unwrap();
+
+		assert_none!(store.load(&session_key).await.unwrap());
+	}
+
+	#[tokio::test]
+	async fn a_deleted_session_is_not_loaded() {
+		let store = MemorySessionStore::default();
+		let session_key = store.save(state(), &Duration::minutes(5)).await.unwrap();
+
+		store.delete(&session_key).await.unwrap();
+
+		assert_none!(store.load(&session_key).await.unwrap());
+	}
+
+	#[tokio::test]
+	async fn clones_share_the_same_sessions() {
+		let store = MemorySessionStore::default();
+		let session_key = store.save(state(), &Duration::minutes(5)).await.unwrap();
+
+		assert_some!(store.clone().load(&session_key).await.unwrap());
+	}
+}
@@ -0,0 +1,83 @@
+mod memory;
+mod postgres;
+
+use std::collections::HashMap;
+
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sqlx::{Pool, Postgres};
+
+pub use memory::MemorySessionStore;
+pub use postgres::PostgresSessionStore;
+
+use crate::configuration::SessionStoreSettings;
+
+type SessionState = HashMap<String, String>;
+
+/// The session store selected by `application.session_store`.
+#[derive(Clone)]
+pub enum SessionStoreBackend {
+	Postgres(PostgresSessionStore),
+	Memory(MemorySessionStore),
+}
+
+impl SessionStoreBackend {
+	pub fn new(settings: &SessionStoreSettings, connection_pool: Pool<Postgres>) -> Self {
+		match settings {
+			SessionStoreSettings::Postgres => Self::Postgres(PostgresSessionStore::new(connection_pool)),
+			SessionStoreSettings::Memory => Self::Memory(MemorySessionStore::default()),
+		}
+	}
+}
+
+impl SessionStore for SessionStoreBackend {
+	async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+		match self {
+			Self::Postgres(store) => store.load(session_key).await,
+			Self::Memory(store) => store.load(session_key).await,
+		}
+	}
+
+	async fn save(&self, session_state: SessionState, ttl: &Duration) -> Result<SessionKey, SaveError> {
+		match self {
+			Self::Postgres(store) => store.save(session_state, ttl).await,
+			Self::Memory(store) => store.save(session_state, ttl).await,
+		}
+	}
+
+	async fn update(
+		&self,
+		session_key: SessionKey,
+		session_state: SessionState,
+		ttl: &Duration,
+	) -> Result<SessionKey, UpdateError> {
+		match self {
+			Self::Postgres(store) => store.update(session_key, session_state, ttl).await,
+			Self::Memory(store) => store.update(session_key, session_state, ttl).await,
+		}
+	}
+
+	async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> Result<(), anyhow::Error> {
+		match self {
+			Self::Postgres(store) => store.update_ttl(session_key, ttl).await,
+			Self::Memory(store) => store.update_ttl(session_key, ttl).await,
+		}
+	}
+
+	async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+		match self {
+			Self::Postgres(store) => store.delete(session_key).await,
+			Self::Memory(store) => store.delete(session_key).await,
+		}
+	}
+}
+
+fn generate_session_key() -> SessionKey {
+	let mut rng = thread_rng();
+	let key: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+		.map(char::from)
+		.take(64)
+		.collect();
+	key.try_into().expect("A 64 character key is a valid session key")
+}
@@ -0,0 +1,131 @@
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use anyhow::Context;
+use sqlx::{Pool, Postgres};
+
+use super::{generate_session_key, SessionState};
+
+/// Keeps session state in the `sessions` table, so that sessions survive
+/// restarts and are shared between replicas.
+#[derive(Clone)]
+pub struct PostgresSessionStore {
+	connection_pool: Pool<Postgres>,
+}
+
+impl PostgresSessionStore {
+	pub fn new(connection_pool: Pool<Postgres>) -> Self {
+		Self { connection_pool }
+	}
+}
+
+impl SessionStore for PostgresSessionStore {
+	async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+		let row = sqlx::query!(
+			r#"
+			SELECT state
+			FROM sessions
+			WHERE session_key = $1 AND expires_at > now()
+			"#,
+			session_key.as_ref()
+		)
+		.fetch_optional(&self.connection_pool)
+		.await
+		.context("Failed to load the session state.")
+		.map_err(LoadError::Other)?;
+		match row {
+			None => Ok(None),
+			Some(row) => serde_json::from_value(row.state)
+				.context("Failed to deserialize the session state.")
+				.map_err(LoadError::Deserialization),
+		}
+	}
+
+	async fn save(&self, session_state: SessionState, ttl: &Duration) -> Result<SessionKey, SaveError> {
+		let state = serde_json::to_value(&session_state)
+			.context("Failed to serialize the session state.")
+			.map_err(SaveError::Serialization)?;
+		let session_key = generate_session_key();
+		sqlx::query!(
+			"DELETE FROM sessions WHERE expires_at <= now()"
+		)
+		.execute(&self.connection_pool)
+		.await
+		.context("Failed to delete expired sessions.")
+		.map_err(SaveError::Other)?;
+		sqlx::query!(
+			r#"
+			INSERT INTO sessions (session_key, state, expires_at)
+			VALUES ($1, $2, now() + make_interval(secs => $3))
+			"#,
+			session_key.as_ref(),
+			state,
+			ttl.as_seconds_f64()
+		)
+		.execute(&self.connection_pool)
+		.await
+		.context("Failed to save the session state.")
+		.map_err(SaveError::Other)?;
+		Ok(session_key)
+	}
+
+	async fn update(
+		&self,
+		session_key: SessionKey,
+		session_state: SessionState,
+		ttl: &Duration,
+	) -> Result<SessionKey, UpdateError> {
+		let state = serde_json::to_value(&session_state)
+			.context("Failed to serialize the session state.")
+			.map_err(UpdateError::Serialization)?;
+		let n_updated_rows = sqlx::query!(
+			r#"
+			UPDATE sessions
+			SET state = $2, expires_at = now() + make_interval(secs => $3)
+			WHERE session_key = $1
+			"#,
+			session_key.as_ref(),
+			state,
+			ttl.as_seconds_f64()
+		)
+		.execute(&self.connection_pool)
+		.await
+		.context("Failed to update the session state.")
+		.map_err(UpdateError::Other)?
+		.rows_affected();
+		if n_updated_rows == 0 {
+			// The session expired in the meantime: start a fresh one.
+			return self.save(session_state, ttl).await.map_err(|e| match e {
+				SaveError::Serialization(e) => UpdateError::Serialization(e),
+				SaveError::Other(e) => UpdateError::Other(e),
+			});
+		}
+		Ok(session_key)
+	}
+
+	async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> Result<(), anyhow::Error> {
+		sqlx::query!(
+			r#"
+			UPDATE sessions
+			SET expires_at = now() + make_interval(secs => $2)
+			WHERE session_key = $1
+			"#,
+			session_key.as_ref(),
+			ttl.as_seconds_f64()
+		)
+		.execute(&self.connection_pool)
+		.await
+		.context("Failed to update the session TTL.")?;
+		Ok(())
+	}
+
+	async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+		sqlx::query!(
+			"DELETE FROM sessions WHERE session_key = $1",
+			session_key.as_ref()
+		)
+		.execute(&self.connection_pool)
+		.await
+		.context("Failed to delete the session.")?;
+		Ok(())
+	}
+}
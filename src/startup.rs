@@ -1,31 +1,111 @@
+use actix_session::SessionMiddleware;
+use actix_web::cookie::Key;
 use actix_web::dev::Server;
 use actix_web::{web, App, HttpServer};
+use actix_web_flash_messages::storage::CookieMessageStore;
+use actix_web_flash_messages::FlashMessagesFramework;
+use actix_web_lab::middleware::from_fn;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Pool, Postgres};
 use tracing_actix_web::TracingLogger;
 use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
 
+use crate::authentication::reject_anonymous_users;
+use crate::configuration::ApplicationSettings;
 use crate::email_client::EmailClient;
-use crate::routes::{confirm, health_check, subscribe};
+use crate::email_templates::EmailTemplates;
+use crate::idempotency::{honor_idempotency_key, IdempotencyTtl};
+use crate::migrations::{ensure_schema_is_not_newer, run_migrations};
+use crate::routes::{
+	add_suppressed_address, admin_dashboard, change_password, change_password_form, confirm, health_check,
+	liveness, log_out, login, login_form, postmark_webhook, publish_newsletter, publish_newsletter_form, readiness,
+	remove_suppressed_address, resend_confirmation, subscribe, suppressed_addresses_page, unsubscribe,
+	unsubscribe_form, EmailProbeCache,
+};
+use crate::session_store::SessionStoreBackend;
 
 pub fn run(
 	listener: TcpListener,
 	connection_pool: Pool<Postgres>,
 	email_client: EmailClient,
-	base_url: String,
+	email_templates: EmailTemplates,
+	session_store: SessionStoreBackend,
+	config: ApplicationSettings,
+	shutdown: ShutdownHandle,
 ) -> Result<Server, std::io::Error> {
 	let connection_pool = web::Data::new(connection_pool);
 	let email_client = web::Data::new(email_client);
-	let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+	let email_templates = web::Data::new(email_templates);
+	let idempotency_ttl = web::Data::new(IdempotencyTtl(config.idempotency_ttl()));
+	let confirmation_token_ttl = web::Data::new(ConfirmationTokenTtl(config.confirmation_token_ttl()));
+	let confirmation_resend_cooldown = web::Data::new(ConfirmationResendCooldown(config.confirmation_resend_cooldown()));
+	let postmark_webhook_settings = web::Data::new(config.postmark_webhook);
+	let email_probe_cache = web::Data::new(EmailProbeCache::new(config.health.email_probe_cache_ttl()));
+	let health_settings = web::Data::new(config.health);
+	let shutdown = web::Data::new(shutdown);
+	let base_url = web::Data::new(ApplicationBaseUrl(config.base_url));
+	let secret_key = Key::from(config.hmac_secret.expose_secret().as_bytes());
+	let hmac_secret = web::Data::new(HmacSecret(config.hmac_secret));
+	let message_store = CookieMessageStore::builder(secret_key.clone()).build();
+	let message_framework = FlashMessagesFramework::builder(message_store).build();
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(message_framework.clone())
+            .wrap(SessionMiddleware::new(session_store.clone(), secret_key.clone()))
             .wrap(TracingLogger::default())
             .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
+            .route("/health/live", web::get().to(liveness))
+            .route("/health/ready", web::get().to(readiness))
+            .route("/login", web::get().to(login_form))
+            .route("/login", web::post().to(login))
+            .service(
+                web::resource("/subscriptions")
+                    .wrap(from_fn(honor_idempotency_key))
+                    .route(web::post().to(subscribe))
+            )
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .service(
+                web::resource("/subscriptions/resend")
+                    .wrap(from_fn(honor_idempotency_key))
+                    .route(web::post().to(resend_confirmation))
+            )
+            .route("/subscriptions/unsubscribe", web::get().to(unsubscribe_form))
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/webhooks/postmark", web::post().to(postmark_webhook))
+            .service(
+                web::scope("/admin")
+                    .wrap(from_fn(reject_anonymous_users))
+                    .route("/dashboard", web::get().to(admin_dashboard))
+                    .service(
+                        web::resource("/newsletters")
+                            .route(web::get().to(publish_newsletter_form))
+                            .route(web::post().to(publish_newsletter).wrap(from_fn(honor_idempotency_key)))
+                    )
+                    .route("/password", web::get().to(change_password_form))
+                    .route("/password", web::post().to(change_password))
+                    .route("/suppressions", web::get().to(suppressed_addresses_page))
+                    .route("/suppressions", web::post().to(add_suppressed_address))
+                    .route("/suppressions/remove", web::post().to(remove_suppressed_address))
+                    .route("/logout", web::post().to(log_out))
+            )
             .app_data(connection_pool.clone())
             .app_data(email_client.clone())
+            .app_data(email_templates.clone())
             .app_data(base_url.clone())
+            .app_data(idempotency_ttl.clone())
+            .app_data(hmac_secret.clone())
+            .app_data(confirmation_token_ttl.clone())
+            .app_data(confirmation_resend_cooldown.clone())
+            .app_data(postmark_webhook_settings.clone())
+            .app_data(health_settings.clone())
+            .app_data(email_probe_cache.clone())
+            .app_data(shutdown.clone())
     })
+    // `Application::run_until_stopped` handles the signals, to drain before stopping.
+    .disable_signals()
     .listen(listener)?
     .run();
 
@@ -35,40 +115,128 @@ pub fn run(
 pub struct Application {
 	pub port: u16,
 	pub server: Server,
+	email_client: EmailClient,
+	shutdown: ShutdownHandle,
+	shutdown_drain_period: std::time::Duration,
 }
 
 impl Application {
 	pub async fn build(
 		config: crate::configuration::Settings,
-	) -> Result<Self, std::io::Error> {
+	) -> Result<Self, anyhow::Error> {
+		// An older build must not serve a schema it doesn't know, whether or not it migrates.
+		if config.application.run_migrations {
+			run_migrations(&config.database).await?;
+		} else {
+			ensure_schema_is_not_newer(&config.database).await?;
+		}
 		let connection_pool: Pool<Postgres> = get_connection_pool(config.database);
-	
-		let sender_email = config.email_client.sender().unwrap();
-		let timeout = config.email_client.timeout();
-		let email_client = EmailClient::new(
-			config.email_client.base_url,
-			sender_email,
-			config.email_client.authorization_token,
-			timeout,
-		);
-	
+		let email_client = config.email_client.client()?;
+		let email_templates = EmailTemplates::load(&config.application.email_templates_directory)?;
+		let session_store = SessionStoreBackend::new(&config.application.session_store, connection_pool.clone());
+
 		let listener = TcpListener::bind(format!("{}:{}", config.application.host, config.application.port))?;
 		let port = listener.local_addr().unwrap().port();
-		let server = run(listener, connection_pool, email_client, config.application.base_url)?;
-		Ok(Self { port, server })
+		let shutdown = ShutdownHandle::default();
+		let shutdown_drain_period = config.application.health.shutdown_drain_period();
+		let server = run(
+			listener,
+			connection_pool,
+			email_client.clone(),
+			email_templates,
+			session_store,
+			config.application,
+			shutdown.clone(),
+		)?;
+		Ok(Self {
+			port,
+			server,
+			email_client,
+			shutdown,
+			shutdown_drain_period,
+		})
 	}
 
 	pub fn port(&self) -> u16 {
 		self.port
 	}
 
+	/// A handle on the client the API sends with, sharing its rate limiter.
+	pub fn email_client(&self) -> EmailClient {
+		self.email_client.clone()
+	}
+
+	/// Triggers the same graceful shutdown as SIGTERM.
+	pub fn shutdown_handle(&self) -> ShutdownHandle {
+		self.shutdown.clone()
+	}
+
+	/// Serves until SIGTERM, Ctrl-C or `ShutdownHandle::begin_shutdown`, then fails readiness
+	/// for `application.health.shutdown_drain_seconds` before finishing in-flight requests.
 	pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-		self.server.await
+		let mut server = self.server;
+		tokio::select! {
+			outcome = &mut server => return outcome,
+			_ = shutdown_signal() => self.shutdown.begin_shutdown(),
+			_ = self.shutdown.requested() => {}
+		}
+		tracing::info!(
+			drain_seconds = self.shutdown_drain_period.as_secs(),
+			"Shutting down. Failing readiness while load balancers drain the traffic."
+		);
+		tokio::time::sleep(self.shutdown_drain_period).await;
+		// The stop command is queued right away, but only handled while the server is polled:
+		// awaiting the server, rather than the returned future, lets in-flight requests finish.
+		drop(server.handle().stop(true));
+		server.await
 	}
 }
 
+/// Shared by the application and its readiness probe, which fails once a shutdown has begun.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+	draining: Arc<AtomicBool>,
+	requested: Arc<Notify>,
+}
+
+impl ShutdownHandle {
+	pub fn begin_shutdown(&self) {
+		self.draining.store(true, Ordering::SeqCst);
+		// `notify_one` stores a permit, so a shutdown requested before anyone waits is not lost.
+		self.requested.notify_one();
+	}
+
+	pub fn is_draining(&self) -> bool {
+		self.draining.load(Ordering::SeqCst)
+	}
+
+	async fn requested(&self) {
+		self.requested.notified().await
+	}
+}
+
+async fn shutdown_signal() {
+	#[cfg(unix)]
+	{
+		let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+			.expect("Failed to install the SIGTERM handler.");
+		tokio::select! {
+			_ = sigterm.recv() => {}
+			_ = tokio::signal::ctrl_c() => {}
+		}
+	}
+	#[cfg(not(unix))]
+	let _ = tokio::signal::ctrl_c().await;
+}
+
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct HmacSecret(pub Secret<String>);
+
+pub struct ConfirmationTokenTtl(pub std::time::Duration);
+
+pub struct ConfirmationResendCooldown(pub std::time::Duration);
+
 pub fn get_connection_pool(config: crate::configuration::DatabaseSettings) -> Pool<Postgres> {
 	Pool::connect_lazy_with(config.with_db())
 }
\ No newline at end of file
@@ -0,0 +1,101 @@
+//! Addresses we must never email again, whatever their subscription status.
+//!
+//! Entries come from Postmark bounce and complaint webhooks or are added by an admin,
+//! e.g. after a legal request. Addresses are stored lowercased.
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, Pool, Postgres};
+
+pub struct SuppressedAddress {
+	pub email: String,
+	pub reason: String,
+	pub suppressed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Checking the suppression list", skip(connection_pool))]
+pub async fn is_suppressed(connection_pool: &Pool<Postgres>, email: &str) -> Result<bool, sqlx::Error> {
+	let suppressed = sqlx::query_scalar!(
+		r#"SELECT EXISTS(SELECT 1 FROM suppressed_addresses WHERE email = lower($1)) AS "suppressed!""#,
+		email
+	)
+	.fetch_one(connection_pool)
+	.await?;
+	Ok(suppressed)
+}
+
+/// Returns those of `emails` on the suppression list, lowercased, in a single query.
+#[tracing::instrument(name = "Checking addresses against the suppression list", skip_all)]
+pub async fn suppressed_among(
+	executor: impl PgExecutor<'_>,
+	emails: &[String],
+) -> Result<HashSet<String>, sqlx::Error> {
+	let suppressed = sqlx::query_scalar!(
+		"SELECT email FROM suppressed_addresses WHERE email IN (SELECT lower(e) FROM unnest($1::text[]) AS e)",
+		emails
+	)
+	.fetch_all(executor)
+	.await?;
+	Ok(suppressed.into_iter().collect())
+}
+
+/// Adds `email` to the suppression list. The original reason is kept if it is already there.
+#[tracing::instrument(name = "Suppressing an address", skip(executor))]
+pub async fn suppress_address(
+	executor: impl PgExecutor<'_>,
+	email: &str,
+	reason: &str,
+) -> Result<(), sqlx::Error> {
+	sqlx::query!(
+		r#"
+		INSERT INTO suppressed_addresses (email, reason, suppressed_at)
+		VALUES (lower($1), $2, now())
+		ON CONFLICT (email) DO NOTHING
+		"#,
+		email,
+		reason
+	)
+	.execute(executor)
+	.await?;
+	Ok(())
+}
+
+/// Returns whether `email` was on the suppression list.
+///
+/// A subscriber who bounced or complained is moved to `unsubscribed`, so they can sign up
+/// again through double opt-in.
+#[tracing::instrument(name = "Removing an address from the suppression list", skip(connection_pool))]
+pub async fn remove_suppressed_address(connection_pool: &Pool<Postgres>, email: &str) -> Result<bool, sqlx::Error> {
+	let mut transaction = connection_pool.begin().await?;
+	let result = sqlx::query!(
+		"DELETE FROM suppressed_addresses WHERE email = lower($1)",
+		email
+	)
+	.execute(&mut *transaction)
+	.await?;
+	if result.rows_affected() == 0 {
+		return Ok(false);
+	}
+	sqlx::query!(
+		r#"
+		UPDATE subscriptions
+		SET status = 'unsubscribed', unsubscribed_at = now()
+		WHERE lower(email) = lower($1) AND status IN ('bounced', 'complained')
+		"#,
+		email
+	)
+	.execute(&mut *transaction)
+	.await?;
+	transaction.commit().await?;
+	Ok(true)
+}
+
+#[tracing::instrument(name = "Listing suppressed addresses", skip(connection_pool))]
+pub async fn list_suppressed_addresses(connection_pool: &Pool<Postgres>) -> Result<Vec<SuppressedAddress>, sqlx::Error> {
+	sqlx::query_as!(
+		SuppressedAddress,
+		"SELECT email, reason, suppressed_at FROM suppressed_addresses ORDER BY suppressed_at DESC, email"
+	)
+	.fetch_all(connection_pool)
+	.await
+}
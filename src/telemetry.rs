@@ -18,3 +18,12 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
 	LogTracer::init().expect("Failed to set logger.");
 	set_global_default(subscriber.into()).expect("Failed to set subscriber.");
 }
+
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> tokio::task::JoinHandle<R>
+where
+	F: FnOnce() -> R + Send + 'static,
+	R: Send + 'static,
+{
+	let current_span = tracing::Span::current();
+	tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
@@ -0,0 +1,95 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Labels both the signing key and the signed message. Bumping it invalidates every
+/// unsubscribe link sent so far, without touching the sessions.
+const TOKEN_PURPOSE: &str = "unsubscribe-v1";
+
+/// Signs `subscriber_id` so that unsubscribe links can't be forged for
+/// other subscribers.
+pub fn generate_unsubscribe_token(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> String {
+	hex::encode(token_mac(subscriber_id, hmac_secret).finalize().into_bytes())
+}
+
+pub fn verify_unsubscribe_token(subscriber_id: Uuid, token: &str, hmac_secret: &Secret<String>) -> bool {
+	let token = match hex::decode(token) {
+		Ok(token) => token,
+		Err(_) => return false,
+	};
+	token_mac(subscriber_id, hmac_secret).verify_slice(&token).is_ok()
+}
+
+fn token_mac(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> HmacSha256 {
+	// `hmac_secret` also signs the session and flash message cookies: derive a key of our own.
+	let mut key = HmacSha256::new_from_slice(hmac_secret.expose_secret().as_bytes())
+		.expect("HMAC can take a key of any size");
+	key.update(TOKEN_PURPOSE.as_bytes());
+	let mut mac = HmacSha256::new_from_slice(&key.finalize().into_bytes())
+		.expect("HMAC can take a key of any size");
+	mac.update(TOKEN_PURPOSE.as_bytes());
+	mac.update(b":");
+	mac.update(subscriber_id.as_bytes());
+	mac
+}
+
+pub fn unsubscribe_link(base_url: &str, subscriber_id: Uuid, hmac_secret: &Secret<String>) -> String {
+	format!(
+		"{}/subscriptions/unsubscribe?subscriber_id={}&token={}",
+		base_url,
+		subscriber_id,
+		generate_unsubscribe_token(subscriber_id, hmac_secret)
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use hmac::Mac;
+	use secrecy::{ExposeSecret, Secret};
+	use uuid::Uuid;
+
+	use super::{generate_unsubscribe_token, verify_unsubscribe_token, HmacSha256};
+
+	fn secret() -> Secret<String> {
+		Secret::new("a-long-and-random-secret".into())
+	}
+
+	#[test]
+	fn a_generated_token_is_accepted() {
+		let subscriber_id = Uuid::new_v4();
+		let token = generate_unsubscribe_token(subscriber_id, &secret());
+		assert!(verify_unsubscribe_token(subscriber_id, &token, &secret()));
+	}
+
+	#[test]
+	fn a_token_for_another_subscriber_is_rejected() {
+		let token = generate_unsubscribe_token(Uuid::new_v4(), &secret());
+		assert!(!verify_unsubscribe_token(Uuid::new_v4(), &token, &secret()));
+	}
+
+	#[test]
+	fn a_token_signed_with_another_secret_is_rejected() {
+		let subscriber_id = Uuid::new_v4();
+		let token = generate_unsubscribe_token(subscriber_id, &Secret::new("another-secret".into()));
+		assert!(!verify_unsubscribe_token(subscriber_id, &token, &secret()));
+	}
+
+	#[test]
+	fn tokens_are_not_signed_with_the_raw_secret() {
+		let subscriber_id = Uuid::new_v4();
+		let mut mac = HmacSha256::new_from_slice(secret().expose_secret().as_bytes()).unwrap();
+		mac.update(subscriber_id.as_bytes());
+		let raw_token = hex::encode(mac.finalize().into_bytes());
+
+		assert_ne!(generate_unsubscribe_token(subscriber_id, &secret()), raw_token);
+		assert!(!verify_unsubscribe_token(subscriber_id, &raw_token, &secret()));
+	}
+
+	#[test]
+	fn a_malformed_token_is_rejected() {
+		assert!(!verify_unsubscribe_token(Uuid::new_v4(), "not-hex", &secret()));
+	}
+}
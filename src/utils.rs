@@ -0,0 +1,35 @@
+// Return an opaque 500 while preserving the error root's cause for logging.
+pub fn e500<T>(e: T) -> actix_web::Error
+where
+	T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+	actix_web::error::ErrorInternalServerError(e)
+}
+
+// Return a 400 with the user-representation of the validation error as body.
+pub fn e400<T>(e: T) -> actix_web::Error
+where
+	T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+	actix_web::error::ErrorBadRequest(e)
+}
+
+pub fn see_other(location: &str) -> actix_web::HttpResponse {
+	actix_web::HttpResponse::SeeOther()
+		.insert_header((actix_web::http::header::LOCATION, location))
+		.finish()
+}
+
+// Format an error followed by every error in its `source()` chain, one per line.
+pub fn error_chain_fmt(
+	e: &impl std::error::Error,
+	f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+	writeln!(f, "{}\n", e)?;
+	let mut current = e.source();
+	while let Some(cause) = current {
+		writeln!(f, "Caused by:\n\t{}", cause)?;
+		current = cause.source();
+	}
+	Ok(())
+}
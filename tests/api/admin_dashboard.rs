@@ -0,0 +1,47 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
+	let app = spawn_app().await;
+
+	let response = app.get_admin_dashboard().await;
+
+	assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_dashboard_links_to_the_admin_actions() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+
+	let html_page = app.get_admin_dashboard_html().await;
+
+	assert!(html_page.contains(&format!("Welcome {}!", app.test_user.username)));
+	assert!(html_page.contains(r#"href="/admin/newsletters""#));
+	assert!(html_page.contains(r#"href="/admin/suppressions""#));
+	assert!(html_page.contains(r#"href="/admin/password""#));
+}
+
+#[tokio::test]
+async fn the_username_is_escaped_on_the_dashboard() {
+	let app = spawn_app().await;
+	let username = "<script>alert(1)</script>";
+	sqlx::query!(
+		"UPDATE users SET username = $1 WHERE user_id = $2",
+		username,
+		app.test_user.user_id
+	)
+	.execute(&app.connection_pool)
+	.await
+	.unwrap();
+	app.post_login(&serde_json::json!({
+		"username": username,
+		"password": &app.test_user.password,
+	}))
+	.await;
+
+	let html_page = app.get_admin_dashboard_html().await;
+
+	assert!(html_page.contains("Welcome &lt;script&gt;alert(1)&lt;&#x2f;script&gt;!"));
+	assert!(!html_page.contains(username));
+}
@@ -0,0 +1,30 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_log_out() {
+	let app = spawn_app().await;
+
+	let response = app.post_logout().await;
+
+	assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn logout_clears_session_state() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+
+	let response = app.post_logout().await;
+	assert_is_redirect_to(&response, "/login");
+
+	let html_page = app.get_login_html().await;
+	assert!(html_page.contains(r#"<p><i>You have successfully logged out.</i></p>"#));
+
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	let response = app.post_newsletters(&newsletter_request_body).await;
+	assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,210 @@
+use wiremock::{matchers::{any, method, path}, Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, AcceptBatch, TestApp};
+
+async fn suppress(app: &TestApp, email: &str) {
+	let response = app
+		.post_suppressions(&serde_json::json!({
+			"email": email,
+			"reason": "Legal request",
+		}))
+		.await;
+	assert_is_redirect_to(&response, "/admin/suppressions");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_suppression_list() {
+	let app = spawn_app().await;
+
+	let response = app.get_suppressions().await;
+
+	assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_suppress_an_address() {
+	let app = spawn_app().await;
+
+	let response = app
+		.post_suppressions(&serde_json::json!({
+			"email": "ursula@example.com",
+			"reason": "Legal request",
+		}))
+		.await;
+
+	assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_listed_with_their_reason() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+
+	suppress(&app, "Ursula@Example.com").await;
+
+	let html_page = app.get_suppressions_html().await;
+	assert!(html_page.contains("<p><i>Ursula@Example.com will no longer receive any email.</i></p>"));
+	assert!(html_page.contains("<td>ursula@example.com</td>"));
+	assert!(html_page.contains("<td>Legal request</td>"));
+}
+
+#[tokio::test]
+async fn suppressing_an_invalid_address_is_rejected() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+
+	let response = app
+		.post_suppressions(&serde_json::json!({
+			"email": "definitely-not-an-email",
+			"reason": "Legal request",
+		}))
+		.await;
+	assert_is_redirect_to(&response, "/admin/suppressions");
+
+	let html_page = app.get_suppressions_html().await;
+	assert!(html_page.contains("is not a valid subscriber email."));
+	assert!(!html_page.contains("<td>definitely-not-an-email</td>"));
+}
+
+#[tokio::test]
+async fn a_reason_is_required_to_suppress_an_address() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+
+	app.post_suppressions(&serde_json::json!({
+		"email": "ursula@example.com",
+		"reason": "  ",
+	}))
+	.await;
+
+	let html_page = app.get_suppressions_html().await;
+	assert!(html_page.contains("<p><i>You must give a reason for suppressing an address.</i></p>"));
+	assert!(!html_page.contains("<td>ursula@example.com</td>"));
+}
+
+#[tokio::test]
+async fn removed_addresses_are_no_longer_listed() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	suppress(&app, "ursula@example.com").await;
+
+	let response = app.post_remove_suppression("ursula@example.com").await;
+	assert_is_redirect_to(&response, "/admin/suppressions");
+
+	let html_page = app.get_suppressions_html().await;
+	assert!(html_page.contains("<p><i>ursula@example.com has been removed from the suppression list.</i></p>"));
+	assert!(!html_page.contains("<td>ursula@example.com</td>"));
+}
+
+#[tokio::test]
+async fn a_bounced_subscriber_can_subscribe_again_once_removed_from_the_list() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	Mock::given(path("/email"))
+		.and(method("POST"))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(2)
+		.mount(&app.email_server)
+		.await;
+	let body = "name=le%20guin&email=ursula_le_guin%40gmail.com".to_string();
+	app.post_subscriptions(body.clone()).await;
+	app.dispatch_outbox().await;
+	sqlx::query!("UPDATE subscriptions SET status = 'bounced'")
+		.execute(&app.connection_pool)
+		.await
+		.unwrap();
+	suppress(&app, "ursula_le_guin@gmail.com").await;
+
+	app.post_remove_suppression("ursula_le_guin@gmail.com").await;
+	let response = app.post_subscriptions(body).await;
+	app.dispatch_outbox().await;
+
+	assert_eq!(response.status().as_u16(), 200);
+	let status = sqlx::query_scalar!("SELECT status FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+	assert_eq!(status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribing_with_a_suppressed_address_returns_200_without_sending() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	suppress(&app, "ursula_le_guin@gmail.com").await;
+
+	Mock::given(path("/email"))
+		.and(method("POST"))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(0)
+		.mount(&app.email_server)
+		.await;
+	let response = app
+		.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+		.await;
+	app.dispatch_outbox().await;
+
+	assert_eq!(response.status().as_u16(), 200);
+	let n_subscribers = sqlx::query_scalar!("SELECT COUNT(*) FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+	assert_eq!(n_subscribers, Some(0));
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_addresses() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+	let email = sqlx::query_scalar!("SELECT email FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+	suppress(&app, &email).await;
+
+	Mock::given(any())
+		.respond_with(ResponseTemplate::new(200))
+		.expect(0)
+		.mount(&app.email_server)
+		.await;
+	app.post_newsletters(&serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	}))
+	.await;
+	app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_left_out_of_a_batch_to_confirmed_subscribers() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	for _ in 0..3 {
+		create_confirmed_subscriber(&app).await;
+	}
+	let email = sqlx::query_scalar!("SELECT email FROM subscriptions LIMIT 1")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+	suppress(&app, &email.to_uppercase()).await;
+
+	Mock::given(path("/email/batch"))
+		.respond_with(AcceptBatch)
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+	app.post_newsletters(&serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	}))
+	.await;
+	app.dispatch_all_pending_emails().await;
+
+	let batch_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+	let messages: Vec<serde_json::Value> = serde_json::from_slice(&batch_request.body).unwrap();
+	assert_eq!(messages.len(), 2);
+	assert!(messages.iter().all(|message| message["To"] != email.as_str()));
+}
@@ -0,0 +1,165 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_change_password_form() {
+	let app = spawn_app().await;
+
+	let response = app.get_change_password().await;
+
+	assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_change_your_password() {
+	let app = spawn_app().await;
+	let new_password = Uuid::new_v4().to_string();
+
+	let response = app
+		.post_change_password(&serde_json::json!({
+			"current_password": Uuid::new_v4().to_string(),
+			"new_password": &new_password,
+			"new_password_check": &new_password,
+		}))
+		.await;
+
+	assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn new_password_fields_must_match() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+
+	let response = app
+		.post_change_password(&serde_json::json!({
+			"current_password": &app.test_user.password,
+			"new_password": Uuid::new_v4().to_string(),
+			"new_password_check": Uuid::new_v4().to_string(),
+		}))
+		.await;
+	assert_is_redirect_to(&response, "/admin/password");
+
+	let html_page = app.get_change_password_html().await;
+	assert!(html_page.contains(
+		"<p><i>You entered two different new passwords - the field values must match.</i></p>"
+	));
+}
+
+#[tokio::test]
+async fn current_password_must_be_valid() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	let new_password = Uuid::new_v4().to_string();
+
+	let response = app
+		.post_change_password(&serde_json::json!({
+			"current_password": Uuid::new_v4().to_string(),
+			"new_password": &new_password,
+			"new_password_check": &new_password,
+		}))
+		.await;
+	assert_is_redirect_to(&response, "/admin/password");
+
+	let html_page = app.get_change_password_html().await;
+	assert!(html_page.contains("<p><i>The current password is incorrect.</i></p>"));
+}
+
+#[tokio::test]
+async fn new_password_must_respect_the_length_limits() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	let test_cases = vec![
+		("a".repeat(11), "too short"),
+		("a".repeat(129), "too long"),
+	];
+
+	for (new_password, description) in test_cases {
+		let response = app
+			.post_change_password(&serde_json::json!({
+				"current_password": &app.test_user.password,
+				"new_password": &new_password,
+				"new_password_check": &new_password,
+			}))
+			.await;
+		assert_is_redirect_to(&response, "/admin/password");
+
+		let html_page = app.get_change_password_html().await;
+		assert!(
+			html_page.contains("<p><i>The new password must be between 12 and 128 characters long.</i></p>"),
+			"The password was not rejected when it was {}.",
+			description
+		);
+	}
+}
+
+#[tokio::test]
+async fn changing_password_works() {
+	let app = spawn_app().await;
+	let new_password = Uuid::new_v4().to_string();
+	app.test_user.login(&app).await;
+
+	let response = app
+		.post_change_password(&serde_json::json!({
+			"current_password": &app.test_user.password,
+			"new_password": &new_password,
+			"new_password_check": &new_password,
+		}))
+		.await;
+	assert_is_redirect_to(&response, "/admin/password");
+
+	let html_page = app.get_change_password_html().await;
+	assert!(html_page.contains("<p><i>Your password has been changed.</i></p>"));
+
+	let response = app.post_logout().await;
+	assert_is_redirect_to(&response, "/login");
+
+	let response = app
+		.post_login(&serde_json::json!({
+			"username": &app.test_user.username,
+			"password": &new_password,
+		}))
+		.await;
+	assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn changing_password_invalidates_other_sessions() {
+	let app = spawn_app().await;
+	let new_password = Uuid::new_v4().to_string();
+	let other_client = reqwest::Client::builder()
+		.redirect(reqwest::redirect::Policy::none())
+		.cookie_store(true)
+		.build()
+		.unwrap();
+	other_client
+		.post(format!("{}/login", &app.address))
+		.form(&serde_json::json!({
+			"username": &app.test_user.username,
+			"password": &app.test_user.password,
+		}))
+		.send()
+		.await
+		.unwrap();
+	app.test_user.login(&app).await;
+
+	let response = app
+		.post_change_password(&serde_json::json!({
+			"current_password": &app.test_user.password,
+			"new_password": &new_password,
+			"new_password_check": &new_password,
+		}))
+		.await;
+	assert_is_redirect_to(&response, "/admin/password");
+
+	let response = other_client
+		.get(format!("{}/admin/dashboard", &app.address))
+		.send()
+		.await
+		.unwrap();
+	assert_is_redirect_to(&response, "/login");
+
+	let response = app.get_admin_dashboard().await;
+	assert_eq!(response.status().as_u16(), 200);
+}
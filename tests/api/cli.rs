@@ -0,0 +1,176 @@
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
+use zero2prod::{
+	cli::{
+		create_admin, export_subscribers, import_subscribers, list_subscribers, migrate, read_password,
+		send_test_email, ImportReport,
+	},
+	configuration::{configuration_directory, get_configuration_from, Environment},
+	suppression::suppress_address,
+};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app};
+
+#[tokio::test]
+async fn migrate_creates_the_database_and_can_run_again() {
+	let mut config = get_configuration_from(&configuration_directory().unwrap(), Environment::Test).unwrap();
+	config.database.database_name = Uuid::new_v4().to_string();
+
+	migrate(&config.database).await.expect("Failed to create and migrate the database.");
+
+	migrate(&config.database).await.expect("Failed to migrate an up to date database.");
+}
+
+#[tokio::test]
+async fn export_writes_every_subscriber_as_csv() {
+	let app = spawn_app().await;
+	create_confirmed_subscriber(&app).await;
+	create_unconfirmed_subscriber(&app).await;
+
+	let mut csv = Vec::new();
+	let n_subscribers = export_subscribers(&app.connection_pool, &mut csv).await.unwrap();
+
+	assert_eq!(n_subscribers, 2);
+	let csv = String::from_utf8(csv).unwrap();
+	let mut lines = csv.lines();
+	assert_eq!(lines.next(), Some("email,name,status,subscribed_at"));
+	let statuses: Vec<_> = lines.map(|line| line.split(',').nth(2).unwrap()).collect();
+	assert_eq!(statuses, ["confirmed", "pending_confirmation"]);
+}
+
+#[tokio::test]
+async fn an_empty_export_still_has_a_header_row() {
+	let app = spawn_app().await;
+
+	let mut csv = Vec::new();
+	export_subscribers(&app.connection_pool, &mut csv).await.unwrap();
+
+	assert_eq!(String::from_utf8(csv).unwrap(), "email,name,status,subscribed_at\n");
+}
+
+#[tokio::test]
+async fn import_adds_confirmed_subscribers_and_reports_skipped_rows() {
+	let app = spawn_app().await;
+	suppress_address(&app.connection_pool, "octavia@example.com", "Legal request")
+		.await
+		.unwrap();
+	let csv = "\
+email,name,source
+ursula@example.com,Ursula Le Guin,old list
+definitely-not-an-email,Nobody,old list
+octavia@example.com,Octavia Butler,old list
+ursula@example.com,Ursula again,old list
+";
+
+	let report = import_subscribers(&app.connection_pool, csv.as_bytes()).await.unwrap();
+
+	assert_eq!(
+		report,
+		ImportReport {
+			imported: 1,
+			already_subscribed: 1,
+			suppressed: 1,
+			invalid: vec![(3, "definitely-not-an-email is not a valid subscriber email.".into())],
+		}
+	);
+	let subscribers = list_subscribers(&app.connection_pool).await.unwrap();
+	assert_eq!(subscribers.len(), 1);
+	assert_eq!(subscribers[0].email, "ursula@example.com");
+	assert_eq!(subscribers[0].name, "Ursula Le Guin");
+	assert_eq!(subscribers[0].status, "confirmed");
+}
+
+#[tokio::test]
+async fn an_export_can_be_imported_again() {
+	let app = spawn_app().await;
+	create_confirmed_subscriber(&app).await;
+	let mut csv = Vec::new();
+	export_subscribers(&app.connection_pool, &mut csv).await.unwrap();
+	let other_app = spawn_app().await;
+
+	let report = import_subscribers(&other_app.connection_pool, csv.as_slice()).await.unwrap();
+
+	assert_eq!(report.imported, 1);
+	assert!(report.invalid.is_empty());
+}
+
+#[tokio::test]
+async fn send_test_email_goes_through_the_configured_transport() {
+	let app = spawn_app().await;
+	Mock::given(path("/email"))
+		.and(method("POST"))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+
+	send_test_email(&app.email_client, "ursula@example.com").await.unwrap();
+}
+
+#[tokio::test]
+async fn send_test_email_rejects_invalid_addresses() {
+	let app = spawn_app().await;
+
+	assert!(send_test_email(&app.email_client, "definitely-not-an-email").await.is_err());
+}
+
+#[tokio::test]
+async fn migrations_do_not_create_a_default_admin() {
+	let app = spawn_app().await;
+
+	let n_users = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM users WHERE username <> $1"#, app.test_user.username)
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+
+	assert_eq!(n_users, 0);
+}
+
+#[tokio::test]
+async fn a_created_admin_can_log_in() {
+	let app = spawn_app().await;
+	let password = Uuid::new_v4().to_string();
+
+	create_admin(&app.connection_pool, "ursula", Secret::new(password.clone())).await.unwrap();
+
+	let response = app
+		.post_login(&serde_json::json!({"username": "ursula", "password": password}))
+		.await;
+	assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn creating_an_admin_with_a_taken_username_fails() {
+	let app = spawn_app().await;
+	let password = Secret::new(Uuid::new_v4().to_string());
+
+	let outcome = create_admin(&app.connection_pool, &app.test_user.username, password).await;
+
+	assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn creating_an_admin_with_a_short_password_fails() {
+	let app = spawn_app().await;
+
+	let outcome = create_admin(&app.connection_pool, "ursula", Secret::new("short".into())).await;
+
+	assert!(outcome.is_err());
+	let n_users = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM users WHERE username = 'ursula'"#)
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+	assert_eq!(n_users, 0);
+}
+
+#[test]
+fn passwords_are_read_from_a_file_without_the_trailing_newline() {
+	let path = std::env::temp_dir().join(Uuid::new_v4().to_string());
+	std::fs::write(&path, "correct horse battery staple\n").unwrap();
+
+	let password = read_password(Some(&path)).unwrap();
+
+	assert_eq!(secrecy::ExposeSecret::expose_secret(&password), "correct horse battery staple");
+	std::fs::remove_file(&path).unwrap();
+}
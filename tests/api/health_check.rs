@@ -1,4 +1,22 @@
-use crate::helpers::spawn_app;
+use std::net::TcpListener;
+
+use uuid::Uuid;
+use wiremock::{matchers::{header, method, path}, Mock, ResponseTemplate};
+use zero2prod::{
+    configuration::{configuration_directory, get_configuration_from, Environment},
+    email_templates::EmailTemplates,
+    session_store::SessionStoreBackend,
+    startup::{get_connection_pool, run, ShutdownHandle},
+};
+
+use crate::helpers::{spawn_app, spawn_app_with};
+
+async fn get_readiness(address: &str) -> (reqwest::StatusCode, serde_json::Value) {
+    let response = reqwest::get(format!("{}/health/ready", address))
+        .await
+        .expect("Failed to execute request.");
+    (response.status(), response.json().await.unwrap())
+}
 
 #[tokio::test]
 async fn health_check_works() {
@@ -12,5 +30,177 @@ async fn health_check_works() {
         .expect("Failed to execute request.");
 
     assert!(response.status().is_success());
-    assert_eq!(Some(0), response.content_length());
-}
\ No newline at end of file
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["email_transports"],
+        serde_json::json!([{"name": "postmark", "role": "primary", "circuit": "closed"}])
+    );
+}
+
+#[tokio::test]
+async fn health_check_reports_an_open_email_circuit() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // Two sign-ups exhaust three attempts each, more than the failure threshold.
+    for email in ["ursula_le_guin%40gmail.com", "octavia_butler%40gmail.com"] {
+        app.post_subscriptions(format!("name=le%20guin&email={}", email)).await;
+        app.dispatch_outbox().await;
+    }
+    let response = reqwest::get(format!("{}/health_check", app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["email_transports"][0]["circuit"], "open");
+}
+
+#[tokio::test]
+async fn liveness_returns_a_200() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/health/live", app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body, serde_json::json!({"status": "up"}));
+}
+
+#[tokio::test]
+async fn readiness_returns_a_200_when_the_database_is_reachable() {
+    let app = spawn_app().await;
+
+    let (status, body) = get_readiness(&app.address).await;
+
+    assert_eq!(status.as_u16(), 200);
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert!(body["checks"]["database"]["latency_ms"].is_u64());
+    assert!(body["checks"]["database"]["pool"]["max_connections"].as_u64().unwrap() > 0);
+    assert_eq!(body["checks"]["email"]["status"], "up");
+    assert_eq!(
+        body["checks"]["email"]["transports"],
+        serde_json::json!([{"name": "postmark", "role": "primary", "circuit": "closed"}])
+    );
+}
+
+#[tokio::test]
+async fn readiness_returns_a_503_when_the_database_is_unreachable() {
+    let mut config = get_configuration_from(&configuration_directory().unwrap(), Environment::Test).unwrap();
+    config.database.database_name = Uuid::new_v4().to_string();
+    // Nothing listens on the discard port.
+    config.database.port = 9;
+    config.application.health.check_timeout_milliseconds = 200;
+    // `Application::build` refuses to start without checking the schema: serve directly, as
+    // an instance that lost its database after starting would.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    let connection_pool = get_connection_pool(config.database);
+    let server = run(
+        listener,
+        connection_pool.clone(),
+        config.email_client.client().unwrap(),
+        EmailTemplates::load(&config.application.email_templates_directory).unwrap(),
+        SessionStoreBackend::new(&config.application.session_store, connection_pool),
+        config.application,
+        ShutdownHandle::default(),
+    )
+    .expect("Failed to start the server.");
+    let _fut = tokio::spawn(server);
+
+    let (status, body) = get_readiness(&address).await;
+
+    assert_eq!(status.as_u16(), 503);
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(body["checks"]["database"]["status"], "down");
+    // The reason, which may name hosts and databases, is only logged.
+    assert!(body["checks"]["database"].get("error").is_none());
+}
+
+#[tokio::test]
+async fn readiness_probes_the_email_provider_when_enabled() {
+    let app = spawn_app_with(|config| config.application.health.probe_email_provider = true).await;
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .and(header("X-Postmark-Server-Token", "my-secret-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"ID": 1})))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let (status, body) = get_readiness(&app.address).await;
+
+    assert_eq!(status.as_u16(), 200);
+    let transport = &body["checks"]["email"]["transports"][0];
+    assert_eq!(transport["status"], "up");
+    assert!(transport["latency_ms"].is_u64());
+}
+
+#[tokio::test]
+async fn an_unreachable_email_provider_is_reported_without_failing_readiness() {
+    let app = spawn_app_with(|config| config.application.health.probe_email_provider = true).await;
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(401))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let (status, body) = get_readiness(&app.address).await;
+
+    assert_eq!(status.as_u16(), 200);
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["checks"]["email"]["status"], "down");
+    assert_eq!(body["checks"]["email"]["transports"][0]["status"], "down");
+    assert!(body["checks"]["email"]["transports"][0].get("error").is_none());
+}
+
+#[tokio::test]
+async fn readiness_reuses_a_recent_email_probe() {
+    let app = spawn_app_with(|config| config.application.health.probe_email_provider = true).await;
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"ID": 1})))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..3 {
+        let (status, body) = get_readiness(&app.address).await;
+        assert_eq!(status.as_u16(), 200);
+        assert_eq!(body["checks"]["email"]["transports"][0]["status"], "up");
+    }
+}
+
+#[tokio::test]
+async fn readiness_fails_while_draining_for_shutdown() {
+    let app = spawn_app().await;
+
+    app.shutdown.begin_shutdown();
+    let (status, body) = get_readiness(&app.address).await;
+
+    assert_eq!(status.as_u16(), 503);
+    assert_eq!(body["status"], "draining");
+    // The process is still up and serving in-flight traffic.
+    let response = reqwest::get(format!("{}/health/live", app.address))
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_server_stops_after_the_drain_period() {
+    let app = spawn_app_with(|config| config.application.health.shutdown_drain_seconds = 0).await;
+
+    app.shutdown.begin_shutdown();
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    let outcome = reqwest::get(format!("{}/health/live", app.address)).await;
+    assert!(outcome.is_err(), "The server still accepts connections.");
+}
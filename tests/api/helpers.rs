@@ -1,8 +1,11 @@
+use fake::Fake;
 use once_cell::sync::Lazy;
-use sqlx::{postgres::PgPoolOptions, Connection, Executor, PgConnection, Pool, Postgres};
+use reqwest::Url;
+use secrecy::Secret;
+use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero2prod::{configuration::{get_configuration, DatabaseSettings}, startup::{get_connection_pool, Application}, telemetry::{get_subscriber, init_subscriber}};
+use wiremock::{matchers::{method, path}, Mock, MockServer, ResponseTemplate};
+use zero2prod::{authentication::compute_password_hash, cli::migrate, configuration::{configuration_directory, get_configuration_from, DatabaseSettings, Environment, PostmarkWebhookSettings, Settings}, email_client::EmailClient, email_templates::EmailTemplates, issue_delivery_worker::{try_execute_task, ExecutionOutcome}, outbox::try_dispatch_email, startup::{get_connection_pool, Application, ShutdownHandle}, telemetry::{get_subscriber, init_subscriber}};
 
 pub struct ConfirmationLinks {
 	pub html: String,
@@ -14,13 +17,58 @@ pub struct TestApp {
 	pub connection_pool: Pool<Postgres>,
 	pub email_server: MockServer,
 	pub port: u16,
+	pub email_client: EmailClient,
+	pub api_client: reqwest::Client,
+	pub test_user: TestUser,
+	pub base_url: String,
+	pub hmac_secret: Secret<String>,
+	pub email_templates: EmailTemplates,
+	pub postmark_webhook: PostmarkWebhookSettings,
+	pub shutdown: ShutdownHandle,
+}
+
+pub struct TestUser {
+	pub user_id: Uuid,
+	pub username: String,
+	pub password: String,
+}
+
+impl TestUser {
+	pub fn generate() -> Self {
+		Self {
+			user_id: Uuid::new_v4(),
+			username: Uuid::new_v4().to_string(),
+			password: Uuid::new_v4().to_string(),
+		}
+	}
+
+	pub async fn login(&self, app: &TestApp) {
+		app.post_login(&serde_json::json!({
+			"username": &self.username,
+			"password": &self.password,
+		}))
+		.await;
+	}
+
+	async fn store(&self, connection_pool: &Pool<Postgres>) {
+		let password_hash = compute_password_hash(Secret::new(self.password.clone())).unwrap();
+		sqlx::query!(
+			"INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+			self.user_id,
+			self.username,
+			secrecy::ExposeSecret::expose_secret(&password_hash),
+		)
+		.execute(connection_pool)
+		.await
+		.expect("Failed to store test user.");
+	}
 }
 
 impl TestApp {
 	pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
 		println!("Post Address: {}", &self.address);
 		reqwest::Client::new()
-			.post(&format!("{}/subscriptions", &self.address))
+			.post(format!("{}/subscriptions", &self.address))
 			.header("Content-Type", "application/x-www-form-urlencoded")
 			.body(body)
 			.send()
@@ -28,6 +76,194 @@ impl TestApp {
 			.expect("Failed to execute request.")
 	}
 
+	pub async fn post_subscriptions_with_idempotency_key(&self, body: String, idempotency_key: &str) -> reqwest::Response {
+		reqwest::Client::new()
+			.post(format!("{}/subscriptions", &self.address))
+			.header("Content-Type", "application/x-www-form-urlencoded")
+			.header("Idempotency-Key", idempotency_key)
+			.body(body)
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn post_subscriptions_resend(&self, body: String) -> reqwest::Response {
+		reqwest::Client::new()
+			.post(format!("{}/subscriptions/resend", &self.address))
+			.header("Content-Type", "application/x-www-form-urlencoded")
+			.body(body)
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn post_newsletters(&self, body: &serde_json::Value) -> reqwest::Response {
+		self.api_client
+			.post(format!("{}/admin/newsletters", &self.address))
+			.form(body)
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn post_newsletters_with_idempotency_key(&self, body: &serde_json::Value, idempotency_key: &str) -> reqwest::Response {
+		self.api_client
+			.post(format!("{}/admin/newsletters", &self.address))
+			.header("Idempotency-Key", idempotency_key)
+			.form(body)
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+	where
+		Body: serde::Serialize,
+	{
+		self.api_client
+			.post(format!("{}/login", &self.address))
+			.form(body)
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn get_login_html(&self) -> String {
+		self.api_client
+			.get(format!("{}/login", &self.address))
+			.send()
+			.await
+			.expect("Failed to execute request.")
+			.text()
+			.await
+			.unwrap()
+	}
+
+	pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+		self.api_client
+			.get(format!("{}/admin/dashboard", &self.address))
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn get_admin_dashboard_html(&self) -> String {
+		self.get_admin_dashboard().await.text().await.unwrap()
+	}
+
+	pub async fn get_publish_newsletter(&self) -> reqwest::Response {
+		self.api_client
+			.get(format!("{}/admin/newsletters", &self.address))
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn get_publish_newsletter_html(&self) -> String {
+		self.get_publish_newsletter().await.text().await.unwrap()
+	}
+
+	pub async fn get_change_password(&self) -> reqwest::Response {
+		self.api_client
+			.get(format!("{}/admin/password", &self.address))
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn get_change_password_html(&self) -> String {
+		self.get_change_password().await.text().await.unwrap()
+	}
+
+	pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+	where
+		Body: serde::Serialize,
+	{
+		self.api_client
+			.post(format!("{}/admin/password", &self.address))
+			.form(body)
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn get_suppressions(&self) -> reqwest::Response {
+		self.api_client
+			.get(format!("{}/admin/suppressions", &self.address))
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn get_suppressions_html(&self) -> String {
+		self.get_suppressions().await.text().await.unwrap()
+	}
+
+	pub async fn post_suppressions<Body>(&self, body: &Body) -> reqwest::Response
+	where
+		Body: serde::Serialize,
+	{
+		self.api_client
+			.post(format!("{}/admin/suppressions", &self.address))
+			.form(body)
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn post_remove_suppression(&self, email: &str) -> reqwest::Response {
+		self.api_client
+			.post(format!("{}/admin/suppressions/remove", &self.address))
+			.form(&serde_json::json!({ "email": email }))
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn post_logout(&self) -> reqwest::Response {
+		self.api_client
+			.post(format!("{}/admin/logout", &self.address))
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn post_postmark_webhook(&self, body: &serde_json::Value) -> reqwest::Response {
+		reqwest::Client::new()
+			.post(format!("{}/webhooks/postmark", &self.address))
+			.basic_auth(
+				&self.postmark_webhook.username,
+				Some(secrecy::ExposeSecret::expose_secret(&self.postmark_webhook.password)),
+			)
+			.json(body)
+			.send()
+			.await
+			.expect("Failed to execute request.")
+	}
+
+	pub async fn dispatch_outbox(&self) {
+		loop {
+			match try_dispatch_email(&self.connection_pool, &self.email_client).await.unwrap() {
+				ExecutionOutcome::EmptyQueue => break,
+				ExecutionOutcome::Throttled(delay) => tokio::time::sleep(delay).await,
+				ExecutionOutcome::TaskCompleted => {}
+			}
+		}
+	}
+
+	pub async fn dispatch_all_pending_emails(&self) {
+		loop {
+			match try_execute_task(&self.connection_pool, &self.email_client, &self.email_templates, &self.base_url, &self.hmac_secret)
+				.await
+				.unwrap()
+			{
+				ExecutionOutcome::EmptyQueue => break,
+				ExecutionOutcome::Throttled(delay) => tokio::time::sleep(delay).await,
+				ExecutionOutcome::TaskCompleted => {}
+			}
+		}
+	}
+
 	pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
 		let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -61,14 +297,20 @@ static TRACING: Lazy<()> = Lazy::new(|| {
 });
 
 pub async fn spawn_app() -> TestApp {
+	spawn_app_with(|_| {}).await
+}
+
+/// Spawns the application with the test settings as adjusted by `customise`.
+pub async fn spawn_app_with(customise: impl FnOnce(&mut Settings)) -> TestApp {
 	Lazy::force(&TRACING);
 	let email_server = MockServer::start().await;
 
 	let config = {
-		let mut c = get_configuration().expect("Failed to read configuration");
+		let mut c = get_configuration_from(&configuration_directory().unwrap(), Environment::Test)
+			.expect("Failed to read configuration");
 		c.database.database_name = Uuid::new_v4().to_string();
-		c.application.port = 0;
 		c.email_client.base_url = email_server.uri();
+		customise(&mut c);
 		c
 	};
 
@@ -77,36 +319,101 @@ pub async fn spawn_app() -> TestApp {
 	let app = Application::build(config.clone()).await.expect("Failed to build app.");
 	let port = app.port();
 	let address = format!("http://127.0.0.1:{}", app.port());
+	// Share the app's client, as the background workers do, so the tests see its circuit breakers.
+	let email_client = app.email_client();
+	let shutdown = app.shutdown_handle();
 	println!("App Address: {}", address);
 	let _fut = tokio::spawn(app.run_until_stopped());
 
-	TestApp {
+	let api_client = reqwest::Client::builder()
+		.redirect(reqwest::redirect::Policy::none())
+		.cookie_store(true)
+		.build()
+		.unwrap();
+
+	let test_app = TestApp {
 		address,
 		connection_pool: get_connection_pool(config.database),
 		email_server,
 		port,
-	}
+		email_client,
+		api_client,
+		test_user: TestUser::generate(),
+		base_url: config.application.base_url,
+		hmac_secret: config.application.hmac_secret,
+		email_templates: EmailTemplates::load(&config.application.email_templates_directory).unwrap(),
+		postmark_webhook: config.application.postmark_webhook,
+		shutdown,
+	};
+	test_app.test_user.store(&test_app.connection_pool).await;
+	test_app
 }
 
 pub async fn configure_database(config: &DatabaseSettings) -> Pool<Postgres> {
-	let mut connection = PgConnection::connect_with(&config.without_db())
+	migrate(config).await.expect("Failed to create and migrate the database.");
+
+	PgPoolOptions::new()
+		.max_connections(10)
+		.connect_with(config.with_db())
 		.await
-		.expect("Failed to connect to Postgres.");
+		.expect("Failed to connect to Postgres.")
+}
+
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+	let name: String = fake::faker::name::en::Name().fake();
+	let email: String = fake::faker::internet::en::SafeEmail().fake();
+	let body = serde_urlencoded::to_string([("name", name), ("email", email)]).unwrap();
 
-		connection.execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
+	let _mock_guard = Mock::given(path("/email"))
+		.and(method("POST"))
+		.respond_with(ResponseTemplate::new(200))
+		.named("Create unconfirmed subscriber")
+		.expect(1)
+		.mount_as_scoped(&app.email_server)
+		.await;
+	app.post_subscriptions(body)
 		.await
-		.expect("Failed to create database.");
+		.error_for_status()
+		.unwrap();
+	app.dispatch_outbox().await;
 
-	let connection_pool = PgPoolOptions::new()
-		.max_connections(10)
-		.connect_with(config.with_db())
+	let email_request = &app
+		.email_server
+		.received_requests()
 		.await
-		.expect("Failed to connect to Postgres.");
+		.unwrap()
+		.pop()
+		.unwrap();
+	app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+	let confirmation_links = create_unconfirmed_subscriber(app).await;
+	let mut confirmation_link = Url::parse(&confirmation_links.html).unwrap();
+	confirmation_link.set_port(Some(app.port)).unwrap();
 
-	sqlx::migrate!("./migrations")
-		.run(&connection_pool)
+	reqwest::get(confirmation_link)
 		.await
-		.expect("Failed to run migrations.");
+		.unwrap()
+		.error_for_status()
+		.unwrap();
+}
+
+/// Accepts every message of a Postmark `/email/batch` request.
+pub struct AcceptBatch;
+
+impl wiremock::Respond for AcceptBatch {
+	fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+		let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+		let results: Vec<_> = messages
+			.iter()
+			.map(|message| serde_json::json!({"ErrorCode": 0, "Message": "OK", "To": message["To"]}))
+			.collect();
+		ResponseTemplate::new(200).set_body_json(results)
+	}
+}
 
-	connection_pool
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+	assert_eq!(response.status().as_u16(), 303);
+	assert_eq!(response.headers().get("Location").unwrap(), location);
 }
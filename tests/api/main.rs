@@ -1,4 +1,15 @@
 mod helpers;
+mod admin_dashboard;
+mod admin_logout;
+mod admin_suppressions;
+mod change_password;
+mod cli;
 mod health_check;
+mod login;
+mod migrations;
+mod newsletters;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_resend;
+mod subscriptions_unsubscribe;
+mod webhooks;
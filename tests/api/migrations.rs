@@ -0,0 +1,97 @@
+use sqlx::{Connection, Executor, PgConnection};
+use uuid::Uuid;
+use zero2prod::{
+	configuration::{configuration_directory, get_configuration_from, Environment, Settings},
+	migrations::{run_migrations, MigrationError},
+	startup::Application,
+};
+
+use crate::helpers::configure_database;
+
+/// Test settings pointing at a new, empty database, with migrations on startup enabled.
+async fn settings_with_empty_database() -> Settings {
+	let mut config = get_configuration_from(&configuration_directory().unwrap(), Environment::Test).unwrap();
+	config.database.database_name = Uuid::new_v4().to_string();
+	config.application.run_migrations = true;
+	let mut connection = PgConnection::connect_with(&config.database.without_db())
+		.await
+		.expect("Failed to connect to Postgres.");
+	connection
+		.execute(format!(r#"CREATE DATABASE "{}";"#, config.database.database_name).as_str())
+		.await
+		.expect("Failed to create database.");
+	config
+}
+
+async fn n_applied_migrations(config: &Settings) -> i64 {
+	let mut connection = PgConnection::connect_with(&config.database.with_db()).await.unwrap();
+	sqlx::query_scalar("SELECT COUNT(*) FROM _sqlx_migrations WHERE success")
+		.fetch_one(&mut connection)
+		.await
+		.unwrap()
+}
+
+fn n_migrations() -> i64 {
+	std::fs::read_dir("migrations").unwrap().count() as i64
+}
+
+#[tokio::test]
+async fn the_application_applies_the_migrations_on_startup_when_enabled() {
+	let config = settings_with_empty_database().await;
+
+	Application::build(config.clone()).await.expect("Failed to build the application.");
+
+	assert_eq!(n_applied_migrations(&config).await, n_migrations());
+}
+
+#[tokio::test]
+async fn replicas_starting_together_do_not_race_on_migrations() {
+	let config = settings_with_empty_database().await;
+
+	let (first, second) = tokio::join!(run_migrations(&config.database), run_migrations(&config.database));
+
+	first.expect("The first replica failed to migrate.");
+	second.expect("The second replica failed to migrate.");
+	assert_eq!(n_applied_migrations(&config).await, n_migrations());
+}
+
+#[tokio::test]
+async fn the_application_refuses_to_start_on_a_newer_schema() {
+	let config = settings_with_empty_database().await;
+	let connection_pool = configure_database(&config.database).await;
+	sqlx::query(
+		r#"
+		INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+		VALUES (99990101000000, 'from a newer build', true, '\x00', 0)
+		"#,
+	)
+	.execute(&connection_pool)
+	.await
+	.unwrap();
+
+	let error = run_migrations(&config.database).await.unwrap_err();
+	assert!(matches!(error, MigrationError::SchemaTooNew { database_version: 99990101000000, .. }));
+	assert!(Application::build(config).await.is_err());
+}
+
+#[tokio::test]
+async fn the_application_refuses_to_start_on_a_newer_schema_even_without_migrating() {
+	let mut config = settings_with_empty_database().await;
+	config.application.run_migrations = false;
+	let connection_pool = configure_database(&config.database).await;
+	sqlx::query(
+		r#"
+		INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+		VALUES (99990101000000, 'from a newer build', true, '\x00', 0)
+		"#,
+	)
+	.execute(&connection_pool)
+	.await
+	.unwrap();
+
+	let error = Application::build(config).await.err().expect("The application started on a newer schema.");
+	assert!(matches!(
+		error.downcast_ref::<MigrationError>(),
+		Some(MigrationError::SchemaTooNew { database_version: 99990101000000, .. })
+	));
+}
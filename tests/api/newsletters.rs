@@ -0,0 +1,355 @@
+use uuid::Uuid;
+use wiremock::{matchers::{any, method, path}, Mock, ResponseTemplate};
+use zero2prod::{email_client::RateLimiter, issue_delivery_worker::{try_execute_task, ExecutionOutcome}};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app, AcceptBatch};
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_unconfirmed_subscriber(&app).await;
+
+	Mock::given(any())
+		.respond_with(ResponseTemplate::new(200))
+		.expect(0)
+		.mount(&app.email_server)
+		.await;
+
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	let response = app.post_newsletters(&newsletter_request_body).await;
+
+	assert_is_redirect_to(&response, "/admin/newsletters");
+	app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(AcceptBatch)
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	let response = app.post_newsletters(&newsletter_request_body).await;
+
+	assert_is_redirect_to(&response, "/admin/newsletters");
+	app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_with_an_invalid_stored_email_are_skipped() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+	sqlx::query!(
+		r#"
+		INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+		VALUES ($1, 'definitely-not-an-email', 'Broken', now(), 'confirmed')
+		"#,
+		Uuid::new_v4()
+	)
+	.execute(&app.connection_pool)
+	.await
+	.expect("Failed to insert a subscriber with an invalid email.");
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(AcceptBatch)
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	let response = app.post_newsletters(&newsletter_request_body).await;
+
+	assert_is_redirect_to(&response, "/admin/newsletters");
+	app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_returns_400_for_invalid_data() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	let test_cases = vec![
+		(
+			serde_json::json!({
+				"text_content": "Newsletter body as plain text",
+				"html_content": "<p>Newsletter body as HTML</p>",
+			}),
+			"missing title",
+		),
+		(
+			serde_json::json!({"title": "Newsletter!"}),
+			"missing content",
+		),
+	];
+
+	for (invalid_body, error_message) in test_cases {
+		let response = app.post_newsletters(&invalid_body).await;
+
+		assert_eq!(
+			400,
+			response.status().as_u16(),
+			"The API did not fail with 400 Bad Request when the payload was {}.",
+			error_message
+		);
+	}
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_rescheduled() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+
+	// The email client exhausts its own retries before the task is handed back to the queue.
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(ResponseTemplate::new(500))
+		.expect(3)
+		.mount(&app.email_server)
+		.await;
+
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	let response = app.post_newsletters(&newsletter_request_body).await;
+	assert_is_redirect_to(&response, "/admin/newsletters");
+	app.dispatch_all_pending_emails().await;
+
+	let task = sqlx::query!("SELECT n_retries, execute_after > now() AS \"postponed!\" FROM issue_delivery_queue")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch the delivery task.");
+	assert_eq!(task.n_retries, 1);
+	assert!(task.postponed);
+}
+
+#[tokio::test]
+async fn batches_accepted_with_an_unreadable_answer_are_not_sent_again() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	app.post_newsletters(&newsletter_request_body).await;
+	app.dispatch_all_pending_emails().await;
+
+	let n_tasks = sqlx::query_scalar!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+	assert_eq!(n_tasks, 0);
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_all_confirmed_subscribers_in_one_batch() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	for _ in 0..3 {
+		create_confirmed_subscriber(&app).await;
+	}
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(AcceptBatch)
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	app.post_newsletters(&newsletter_request_body).await;
+	app.dispatch_all_pending_emails().await;
+
+	let batch_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+	let messages: Vec<serde_json::Value> = serde_json::from_slice(&batch_request.body).unwrap();
+	assert_eq!(messages.len(), 3);
+}
+
+#[tokio::test]
+async fn messages_rejected_permanently_within_a_batch_are_not_retried() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+			{"ErrorCode": 406, "Message": "Inactive recipient"}
+		])))
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	app.post_newsletters(&newsletter_request_body).await;
+	app.dispatch_all_pending_emails().await;
+
+	let n_tasks = sqlx::query_scalar!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+	assert_eq!(n_tasks, 0);
+}
+
+#[tokio::test]
+async fn messages_throttled_within_a_batch_are_rescheduled() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+			{"ErrorCode": 429, "Message": "Rate limit exceeded"}
+		])))
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	app.post_newsletters(&newsletter_request_body).await;
+	app.dispatch_all_pending_emails().await;
+
+	let task = sqlx::query!("SELECT n_retries FROM issue_delivery_queue")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch the delivery task.");
+	assert_eq!(task.n_retries, 1);
+}
+
+#[tokio::test]
+async fn workers_back_off_without_failing_when_the_send_rate_is_exhausted() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	for _ in 0..3 {
+		create_confirmed_subscriber(&app).await;
+	}
+	let email_client = app.email_client.clone().with_rate_limiter(RateLimiter::new(2., 1));
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(AcceptBatch)
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	app.post_newsletters(&newsletter_request_body).await;
+	let execute_task = || try_execute_task(&app.connection_pool, &email_client, &app.email_templates, &app.base_url, &app.hmac_secret);
+	assert!(matches!(execute_task().await.unwrap(), ExecutionOutcome::TaskCompleted));
+	assert!(matches!(execute_task().await.unwrap(), ExecutionOutcome::Throttled(_)));
+
+	let batch_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+	let messages: Vec<serde_json::Value> = serde_json::from_slice(&batch_request.body).unwrap();
+	assert_eq!(messages.len(), 2);
+	let task = sqlx::query!("SELECT n_retries, execute_after <= now() AS \"due!\" FROM issue_delivery_queue")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch the delivery task.");
+	assert_eq!(task.n_retries, 0);
+	assert!(task.due);
+}
+
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(AcceptBatch)
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	let idempotency_key = Uuid::new_v4().to_string();
+
+	let response = app.post_newsletters_with_idempotency_key(&newsletter_request_body, &idempotency_key).await;
+	assert_is_redirect_to(&response, "/admin/newsletters");
+
+	let html_page = app.get_publish_newsletter_html().await;
+	assert!(html_page.contains("<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"));
+
+	let response = app.post_newsletters_with_idempotency_key(&newsletter_request_body, &idempotency_key).await;
+	assert_is_redirect_to(&response, "/admin/newsletters");
+
+	app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_publish_a_newsletter() {
+	let app = spawn_app().await;
+
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	let response = app.post_newsletters(&newsletter_request_body).await;
+
+	assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_newsletter_form() {
+	let app = spawn_app().await;
+
+	let response = app.get_publish_newsletter().await;
+
+	assert_is_redirect_to(&response, "/login");
+}
@@ -1,5 +1,6 @@
 use crate::helpers::spawn_app;
 use sqlx::query;
+use uuid::Uuid;
 use wiremock::{matchers::{path, method}, Mock, ResponseTemplate, http::Method};
 
 #[tokio::test]
@@ -12,6 +13,7 @@ async fn subscribe_returns_a_200_for_valid_form_data() {
 		.mount(&app.email_server)
 		.await;
 	let response = app.post_subscriptions("name=andre&email=andre.heber@gmx.net".to_string()).await;
+	app.dispatch_outbox().await;
 
     assert_eq!(200, response.status().as_u16());
 }
@@ -26,6 +28,7 @@ async fn subscribe_persists_the_new_subscriber() {
 		.mount(&app.email_server)
 		.await;
 	let _response = app.post_subscriptions("name=andre&email=andre.heber@gmx.net".to_string()).await;
+	app.dispatch_outbox().await;
 
 	let saved = query!("SELECT email, name, status FROM subscriptions",)
 		.fetch_one(&app.connection_pool)
@@ -94,6 +97,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
 		.await;
 
 	let _response = app.post_subscriptions(body.to_string()).await;
+	app.dispatch_outbox().await;
 }
 
 #[tokio::test]
@@ -110,6 +114,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 		.await;
 
 	let response = app.post_subscriptions(body.to_string()).await;
+	app.dispatch_outbox().await;
 
 	assert_eq!(200, response.status().as_u16());
 
@@ -141,6 +146,12 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
 
 	let response = app.post_subscriptions(body.to_string()).await;
 	assert_eq!(response.status().as_u16(), 500);
+	let n_queued_emails = query!("SELECT COUNT(*) AS \"count!\" FROM outbox")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap()
+		.count;
+	assert_eq!(n_queued_emails, 0);
 
 	// Bring the table back for other tests
 	// sqlx::query(include_str!("../../../migrations/redo_subscriptions_table.sql"))
@@ -149,4 +160,287 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
 	// 	.expect("Failed to re-create the subscriptions table");
 
 	// drop(subscribers);
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn subscribe_is_idempotent() {
+	let app = spawn_app().await;
+	let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+	let idempotency_key = Uuid::new_v4().to_string();
+
+	Mock::given(path("/email"))
+		.and(method(Method::POST))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+
+	let response = app.post_subscriptions_with_idempotency_key(body.to_string(), &idempotency_key).await;
+	assert_eq!(200, response.status().as_u16());
+
+	let response = app.post_subscriptions_with_idempotency_key(body.to_string(), &idempotency_key).await;
+	assert_eq!(200, response.status().as_u16());
+	app.dispatch_outbox().await;
+
+	let saved = query!("SELECT count(*) as \"count!\" FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to count saved subscriptions.");
+	assert_eq!(saved.count, 1);
+}
+
+#[tokio::test]
+async fn concurrent_subscribe_submissions_are_handled_gracefully() {
+	let app = spawn_app().await;
+	let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+	let idempotency_key = Uuid::new_v4().to_string();
+
+	Mock::given(path("/email"))
+		.and(method(Method::POST))
+		.respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(2)))
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+
+	let response1 = app.post_subscriptions_with_idempotency_key(body.to_string(), &idempotency_key);
+	let response2 = app.post_subscriptions_with_idempotency_key(body.to_string(), &idempotency_key);
+	let (response1, response2) = tokio::join!(response1, response2);
+
+	assert_eq!(response1.status(), response2.status());
+	assert_eq!(response1.text().await.unwrap(), response2.text().await.unwrap());
+	app.dispatch_outbox().await;
+}
+
+#[tokio::test]
+async fn reusing_an_idempotency_key_for_another_request_is_rejected_with_a_422() {
+	let app = spawn_app().await;
+	let idempotency_key = Uuid::new_v4().to_string();
+	Mock::given(path("/email"))
+		.and(method(Method::POST))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+
+	let response = app
+		.post_subscriptions_with_idempotency_key("name=le%20guin&email=ursula_le_guin%40gmail.com".into(), &idempotency_key)
+		.await;
+	assert_eq!(200, response.status().as_u16());
+	let response = app
+		.post_subscriptions_with_idempotency_key("name=butler&email=octavia_butler%40gmail.com".into(), &idempotency_key)
+		.await;
+	assert_eq!(422, response.status().as_u16());
+	app.dispatch_outbox().await;
+
+	let saved = query!("SELECT email FROM subscriptions")
+		.fetch_all(&app.connection_pool)
+		.await
+		.expect("Failed to fetch saved subscriptions.");
+	assert_eq!(saved.len(), 1);
+	assert_eq!(saved[0].email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn idempotency_keys_of_anonymous_callers_ignore_forwarded_headers() {
+	let app = spawn_app().await;
+	let idempotency_key = Uuid::new_v4().to_string();
+	Mock::given(path("/email"))
+		.and(method(Method::POST))
+		.respond_with(ResponseTemplate::new(200))
+		.mount(&app.email_server)
+		.await;
+	let post = |forwarded_for: &'static str, body: &'static str| {
+		reqwest::Client::new()
+			.post(format!("{}/subscriptions", &app.address))
+			.header("Content-Type", "application/x-www-form-urlencoded")
+			.header("Idempotency-Key", &idempotency_key)
+			.header("X-Forwarded-For", forwarded_for)
+			.body(body)
+			.send()
+	};
+
+	let response = post("203.0.113.1", "name=le%20guin&email=ursula_le_guin%40gmail.com").await.unwrap();
+	assert_eq!(200, response.status().as_u16());
+	// Claiming another address doesn't get the caller a namespace of its own.
+	let response = post("203.0.113.2", "name=butler&email=octavia_butler%40gmail.com").await.unwrap();
+
+	assert_eq!(422, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_200_even_if_the_email_provider_is_down() {
+	let app = spawn_app().await;
+	Mock::given(path("/email"))
+		.and(method(Method::POST))
+		.respond_with(ResponseTemplate::new(500))
+		.expect(3)
+		.mount(&app.email_server)
+		.await;
+
+	let response = app.post_subscriptions("name=andre&email=andre.heber@gmx.net".to_string()).await;
+	assert_eq!(200, response.status().as_u16());
+	app.dispatch_outbox().await;
+
+	// The email client exhausted its retries; the outbox tries again later.
+	let message = query!("SELECT n_attempts, sent_at, failed_at, execute_after > now() AS \"postponed!\" FROM outbox")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch the queued email.");
+	assert_eq!(message.n_attempts, 1);
+	assert!(message.sent_at.is_none() && message.failed_at.is_none());
+	assert!(message.postponed);
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_an_invalid_idempotency_key() {
+	let app = spawn_app().await;
+	let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+	let response = app.post_subscriptions_with_idempotency_key(body.to_string(), "").await;
+
+	assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_sends_a_fresh_confirmation_link() {
+	let app = spawn_app().await;
+	Mock::given(path("/email"))
+		.and(method(Method::POST))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(2)
+		.mount(&app.email_server)
+		.await;
+	let body = "name=andre&email=andre.heber@gmx.net".to_string();
+
+	app.post_subscriptions(body.clone()).await;
+	app.dispatch_outbox().await;
+	// Past the resend cooldown.
+	query!("UPDATE subscription_tokens SET created_at = now() - interval '1 hour'")
+		.execute(&app.connection_pool)
+		.await
+		.unwrap();
+	let response = app.post_subscriptions(body).await;
+	app.dispatch_outbox().await;
+
+	assert_eq!(200, response.status().as_u16());
+	let email_requests = app.email_server.received_requests().await.unwrap();
+	let first_link = app.get_confirmation_links(&email_requests[0]);
+	let second_link = app.get_confirmation_links(&email_requests[1]);
+	assert_ne!(first_link.html, second_link.html);
+	let n_tokens = query!("SELECT COUNT(*) AS \"count!\" FROM subscription_tokens")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap()
+		.count;
+	assert_eq!(n_tokens, 1);
+}
+
+#[tokio::test]
+async fn subscribing_a_confirmed_address_returns_200_without_sending_an_email() {
+	let app = spawn_app().await;
+	Mock::given(path("/email"))
+		.and(method(Method::POST))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+	let body = "name=andre&email=andre.heber@gmx.net".to_string();
+	app.post_subscriptions(body.clone()).await;
+	app.dispatch_outbox().await;
+	query!("UPDATE subscriptions SET status = 'confirmed'")
+		.execute(&app.connection_pool)
+		.await
+		.unwrap();
+
+	let response = app.post_subscriptions(body).await;
+	app.dispatch_outbox().await;
+
+	assert_eq!(200, response.status().as_u16());
+	let saved = query!("SELECT status FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+	assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn an_unsubscribed_address_can_subscribe_again() {
+	let app = spawn_app().await;
+	Mock::given(path("/email"))
+		.and(method(Method::POST))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(2)
+		.mount(&app.email_server)
+		.await;
+	let body = "name=andre&email=andre.heber@gmx.net".to_string();
+	app.post_subscriptions(body).await;
+	query!("UPDATE subscriptions SET status = 'unsubscribed', unsubscribed_at = now()")
+		.execute(&app.connection_pool)
+		.await
+		.unwrap();
+
+	let response = app.post_subscriptions("name=Andre%20Heber&email=andre.heber@gmx.net".to_string()).await;
+	app.dispatch_outbox().await;
+
+	assert_eq!(200, response.status().as_u16());
+	let saved = query!("SELECT name, status, unsubscribed_at FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+	assert_eq!(saved.name, "Andre Heber");
+	assert_eq!(saved.status, "pending_confirmation");
+	assert!(saved.unsubscribed_at.is_none());
+}
+
+#[tokio::test]
+async fn subscribing_again_within_the_resend_cooldown_does_not_send_another_email() {
+	let app = spawn_app().await;
+	Mock::given(path("/email"))
+		.and(method(Method::POST))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+	let body = "name=andre&email=andre.heber@gmx.net".to_string();
+
+	app.post_subscriptions(body.clone()).await;
+	app.dispatch_outbox().await;
+	let response = app.post_subscriptions(body).await;
+	app.dispatch_outbox().await;
+
+	assert_eq!(200, response.status().as_u16());
+	let n_queued = query!("SELECT COUNT(*) AS \"count!\" FROM outbox")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap()
+		.count;
+	assert_eq!(n_queued, 1);
+}
+
+#[tokio::test]
+async fn concurrent_first_time_sign_ups_for_the_same_address_both_succeed() {
+	let app = spawn_app().await;
+	Mock::given(path("/email"))
+		.and(method(Method::POST))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+	let body = "name=andre&email=andre.heber@gmx.net".to_string();
+
+	let (response1, response2, response3) = tokio::join!(
+		app.post_subscriptions(body.clone()),
+		app.post_subscriptions(body.clone()),
+		app.post_subscriptions(body),
+	);
+	app.dispatch_outbox().await;
+
+	for response in [response1, response2, response3] {
+		assert_eq!(200, response.status().as_u16());
+	}
+	let saved = query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+	assert_eq!(saved.count, 1);
+}
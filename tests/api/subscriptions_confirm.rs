@@ -1,7 +1,7 @@
 use reqwest::Url;
 use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
 
-use crate::helpers::spawn_app;
+use crate::helpers::{create_unconfirmed_subscriber, spawn_app};
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -21,6 +21,7 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
 		.await;
 
 	app.post_subscriptions("name=Andre%20Heber&email=andre.heber%40gmx.net".into()).await;
+	app.dispatch_outbox().await;
 	let email_request = &app.email_server.received_requests().await.unwrap()[0];
 	let confirmation_links = app.get_confirmation_links(email_request);
 
@@ -44,3 +45,104 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
 		assert_eq!(saved.name, "Andre Heber");
 		assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_a_410() {
+	let app = spawn_app().await;
+	let confirmation_links = create_unconfirmed_subscriber(&app).await;
+	sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '3 days'")
+		.execute(&app.connection_pool)
+		.await
+		.expect("Failed to backdate the subscription token.");
+	let mut confirmation_link = Url::parse(&confirmation_links.html).unwrap();
+	confirmation_link.set_port(Some(app.port)).unwrap();
+
+	let response = reqwest::get(confirmation_link).await.unwrap();
+
+	assert_eq!(response.status().as_u16(), 410);
+	let saved = sqlx::query!("SELECT status FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch saved subscription");
+	assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn clicking_a_confirmation_link_twice_shows_an_already_confirmed_page() {
+	let app = spawn_app().await;
+	let confirmation_links = create_unconfirmed_subscriber(&app).await;
+	let mut confirmation_link = Url::parse(&confirmation_links.html).unwrap();
+	confirmation_link.set_port(Some(app.port)).unwrap();
+
+	reqwest::get(confirmation_link.clone()).await.unwrap().error_for_status().unwrap();
+	let response = reqwest::get(confirmation_link).await.unwrap();
+
+	assert_eq!(response.status().as_u16(), 200);
+	assert!(response.text().await.unwrap().contains("already confirmed"));
+	let token = sqlx::query!("SELECT used_at FROM subscription_tokens")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch the subscription token.");
+	assert!(token.used_at.is_some());
+}
+
+#[tokio::test]
+async fn a_used_confirmation_link_does_not_resubscribe_an_unsubscribed_subscriber() {
+	let app = spawn_app().await;
+	let confirmation_links = create_unconfirmed_subscriber(&app).await;
+	let mut confirmation_link = Url::parse(&confirmation_links.html).unwrap();
+	confirmation_link.set_port(Some(app.port)).unwrap();
+	reqwest::get(confirmation_link.clone()).await.unwrap().error_for_status().unwrap();
+	sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed', unsubscribed_at = now()")
+		.execute(&app.connection_pool)
+		.await
+		.unwrap();
+
+	reqwest::get(confirmation_link).await.unwrap();
+
+	let saved = sqlx::query!("SELECT status FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch saved subscription");
+	assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn confirmations_with_an_unknown_token_are_rejected_with_a_401() {
+	let app = spawn_app().await;
+
+	let response = reqwest::get(&format!("{}/subscriptions/confirm?subscription_token=unknown", app.address))
+		.await
+		.unwrap();
+
+	assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_bounced_subscriber_clicking_their_link_is_not_confirmed() {
+	let app = spawn_app().await;
+	let confirmation_links = create_unconfirmed_subscriber(&app).await;
+	let mut confirmation_link = Url::parse(&confirmation_links.html).unwrap();
+	confirmation_link.set_port(Some(app.port)).unwrap();
+	sqlx::query!("UPDATE subscriptions SET status = 'bounced'")
+		.execute(&app.connection_pool)
+		.await
+		.unwrap();
+
+	let response = reqwest::get(confirmation_link).await.unwrap();
+
+	assert_eq!(response.status().as_u16(), 200);
+	let page = response.text().await.unwrap();
+	assert!(page.contains("can no longer be confirmed"));
+	assert!(!page.contains("is confirmed"));
+	let saved = sqlx::query!("SELECT status FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch saved subscription");
+	assert_eq!(saved.status, "bounced");
+	let token = sqlx::query!("SELECT used_at FROM subscription_tokens")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch the subscription token.");
+	assert!(token.used_at.is_none());
+}
@@ -0,0 +1,86 @@
+use reqwest::Url;
+use wiremock::{matchers::{any, method, path}, Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, TestApp};
+
+const BODY: &str = "name=Andre%20Heber&email=andre.heber%40gmx.net";
+
+async fn subscribe(app: &TestApp) {
+	Mock::given(path("/email"))
+		.and(method("POST"))
+		.respond_with(ResponseTemplate::new(200))
+		.mount(&app.email_server)
+		.await;
+	app.post_subscriptions(BODY.into()).await.error_for_status().unwrap();
+	app.dispatch_outbox().await;
+}
+
+async fn backdate_tokens(app: &TestApp) {
+	sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '1 hour'")
+		.execute(&app.connection_pool)
+		.await
+		.expect("Failed to backdate the subscription tokens.");
+}
+
+#[tokio::test]
+async fn resend_sends_a_new_confirmation_link_to_a_pending_subscriber() {
+	let app = spawn_app().await;
+	subscribe(&app).await;
+	backdate_tokens(&app).await;
+
+	let response = app.post_subscriptions_resend("email=andre.heber%40gmx.net".into()).await;
+	app.dispatch_outbox().await;
+
+	assert_eq!(response.status().as_u16(), 200);
+	let email_requests = app.email_server.received_requests().await.unwrap();
+	assert_eq!(email_requests.len(), 2);
+	let old_link = app.get_confirmation_links(&email_requests[0]);
+	let new_link = app.get_confirmation_links(&email_requests[1]);
+	assert_ne!(old_link.html, new_link.html);
+
+	let mut old_link = Url::parse(&old_link.html).unwrap();
+	old_link.set_port(Some(app.port)).unwrap();
+	assert_eq!(reqwest::get(old_link).await.unwrap().status().as_u16(), 401);
+
+	let mut new_link = Url::parse(&new_link.html).unwrap();
+	new_link.set_port(Some(app.port)).unwrap();
+	assert_eq!(reqwest::get(new_link).await.unwrap().status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resend_is_rate_limited_per_address() {
+	let app = spawn_app().await;
+	subscribe(&app).await;
+
+	let response = app.post_subscriptions_resend("email=andre.heber%40gmx.net".into()).await;
+	app.dispatch_outbox().await;
+
+	// Indistinguishable from a resend to an address that is not on the list.
+	assert_eq!(response.status().as_u16(), 200);
+	assert!(response.headers().get("Retry-After").is_none());
+	assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn resend_for_an_unknown_address_returns_200_without_sending() {
+	let app = spawn_app().await;
+	Mock::given(any())
+		.respond_with(ResponseTemplate::new(200))
+		.expect(0)
+		.mount(&app.email_server)
+		.await;
+
+	let response = app.post_subscriptions_resend("email=nobody%40example.com".into()).await;
+	app.dispatch_outbox().await;
+
+	assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resend_returns_400_for_an_invalid_email() {
+	let app = spawn_app().await;
+
+	let response = app.post_subscriptions_resend("email=definitely-not-an-email".into()).await;
+
+	assert_eq!(response.status().as_u16(), 400);
+}
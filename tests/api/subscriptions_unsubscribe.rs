@@ -0,0 +1,204 @@
+use reqwest::Url;
+use uuid::Uuid;
+use wiremock::{matchers::{method, path}, Mock};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, AcceptBatch, TestApp};
+
+async fn publish_and_deliver_newsletter(app: &TestApp) {
+	let newsletter_request_body = serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	});
+	app.post_newsletters(&newsletter_request_body).await;
+	app.dispatch_all_pending_emails().await;
+}
+
+fn get_unsubscribe_link(app: &TestApp, email_request: &wiremock::Request) -> Url {
+	let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+	let header = body[0]["Headers"]
+		.as_array()
+		.unwrap()
+		.iter()
+		.find(|h| h["Name"] == "List-Unsubscribe")
+		.expect("No List-Unsubscribe header found.");
+	let link = header["Value"]
+		.as_str()
+		.unwrap()
+		.trim_start_matches('<')
+		.trim_end_matches('>');
+	let mut link = Url::parse(link).unwrap();
+	link.set_port(Some(app.port)).unwrap();
+	link
+}
+
+#[tokio::test]
+async fn newsletters_carry_one_click_unsubscribe_headers() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(AcceptBatch)
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+	publish_and_deliver_newsletter(&app).await;
+
+	let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+	let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+	assert!(body[0]["Headers"]
+		.as_array()
+		.unwrap()
+		.contains(&serde_json::json!({"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"})));
+	let unsubscribe_link = get_unsubscribe_link(&app, &email_request);
+	assert!(body[0]["TextBody"].as_str().unwrap().contains(unsubscribe_link.path()));
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_shows_a_confirmation_page() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(AcceptBatch)
+		.mount(&app.email_server)
+		.await;
+	publish_and_deliver_newsletter(&app).await;
+	let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+	let unsubscribe_link = get_unsubscribe_link(&app, &email_request);
+
+	let response = reqwest::get(unsubscribe_link).await.unwrap();
+
+	assert_eq!(response.status().as_u16(), 200);
+	let saved = sqlx::query!("SELECT status FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch saved subscription.");
+	assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn a_one_click_unsubscribe_request_unsubscribes_the_subscriber() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(AcceptBatch)
+		.mount(&app.email_server)
+		.await;
+	publish_and_deliver_newsletter(&app).await;
+	let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+	let unsubscribe_link = get_unsubscribe_link(&app, &email_request);
+
+	let response = reqwest::Client::new()
+		.post(unsubscribe_link)
+		.header("Content-Type", "application/x-www-form-urlencoded")
+		.body("List-Unsubscribe=One-Click")
+		.send()
+		.await
+		.unwrap();
+
+	assert_eq!(response.status().as_u16(), 200);
+	let saved = sqlx::query!("SELECT status, unsubscribed_at FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch saved subscription.");
+	assert_eq!(saved.status, "unsubscribed");
+	assert!(saved.unsubscribed_at.is_some());
+}
+
+#[tokio::test]
+async fn a_bounced_subscriber_who_unsubscribes_stays_bounced() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(AcceptBatch)
+		.mount(&app.email_server)
+		.await;
+	publish_and_deliver_newsletter(&app).await;
+	let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+	let unsubscribe_link = get_unsubscribe_link(&app, &email_request);
+	sqlx::query!("UPDATE subscriptions SET status = 'bounced'")
+		.execute(&app.connection_pool)
+		.await
+		.unwrap();
+
+	let response = reqwest::Client::new()
+		.post(unsubscribe_link)
+		.header("Content-Type", "application/x-www-form-urlencoded")
+		.body("List-Unsubscribe=One-Click")
+		.send()
+		.await
+		.unwrap();
+
+	assert_eq!(response.status().as_u16(), 200);
+	let saved = sqlx::query!("SELECT status, unsubscribed_at FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch saved subscription.");
+	assert_eq!(saved.status, "bounced");
+	assert!(saved.unsubscribed_at.is_none());
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_further_issues() {
+	let app = spawn_app().await;
+	app.test_user.login(&app).await;
+	create_confirmed_subscriber(&app).await;
+
+	Mock::given(path("/email/batch"))
+		.and(method("POST"))
+		.respond_with(AcceptBatch)
+		.expect(1)
+		.mount(&app.email_server)
+		.await;
+	publish_and_deliver_newsletter(&app).await;
+	let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+	let unsubscribe_link = get_unsubscribe_link(&app, &email_request);
+	reqwest::Client::new()
+		.post(unsubscribe_link)
+		.send()
+		.await
+		.unwrap()
+		.error_for_status()
+		.unwrap();
+
+	publish_and_deliver_newsletter(&app).await;
+}
+
+#[tokio::test]
+async fn unsubscribe_requests_with_an_invalid_token_are_rejected_with_a_401() {
+	let app = spawn_app().await;
+
+	let response = reqwest::Client::new()
+		.post(format!(
+			"{}/subscriptions/unsubscribe?subscriber_id={}&token=deadbeef",
+			app.address,
+			Uuid::new_v4()
+		))
+		.send()
+		.await
+		.unwrap();
+
+	assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn unsubscribe_requests_without_parameters_are_rejected_with_a_400() {
+	let app = spawn_app().await;
+
+	let response = reqwest::get(format!("{}/subscriptions/unsubscribe", app.address))
+		.await
+		.unwrap();
+
+	assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,225 @@
+use wiremock::{matchers::{any, method, path}, Mock, ResponseTemplate};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn confirmed_subscriber_email(app: &TestApp) -> String {
+	create_confirmed_subscriber(app).await;
+	sqlx::query!("SELECT email FROM subscriptions")
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch the subscriber.")
+		.email
+}
+
+async fn subscriber_status(app: &TestApp, email: &str) -> String {
+	sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", email)
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch the subscriber status.")
+		.status
+}
+
+fn bounce(email: &str, bounce_type: &str) -> serde_json::Value {
+	serde_json::json!({
+		"RecordType": "Bounce",
+		"ID": 4323372036854775807_i64,
+		"Type": bounce_type,
+		"MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+		"Email": email,
+		"BouncedAt": "2024-04-15T16:33:54.9070259Z",
+	})
+}
+
+fn spam_complaint(email: &str) -> serde_json::Value {
+	serde_json::json!({
+		"RecordType": "SpamComplaint",
+		"Type": "SpamComplaint",
+		"MessageID": "00000000-0000-0000-0000-000000000000",
+		"Email": email,
+		"BouncedAt": "2024-04-15T16:33:54.9070259Z",
+	})
+}
+
+#[tokio::test]
+async fn requests_without_credentials_are_rejected() {
+	let app = spawn_app().await;
+
+	let response = reqwest::Client::new()
+		.post(format!("{}/webhooks/postmark", &app.address))
+		.json(&bounce("ursula@example.com", "HardBounce"))
+		.send()
+		.await
+		.expect("Failed to execute request.");
+
+	assert_eq!(response.status().as_u16(), 401);
+	assert_eq!(response.headers()["WWW-Authenticate"], r#"Basic realm="postmark""#);
+}
+
+#[tokio::test]
+async fn requests_with_the_wrong_password_are_rejected() {
+	let app = spawn_app().await;
+
+	let response = reqwest::Client::new()
+		.post(format!("{}/webhooks/postmark", &app.address))
+		.basic_auth(&app.postmark_webhook.username, Some("wrong-password"))
+		.json(&bounce("ursula@example.com", "HardBounce"))
+		.send()
+		.await
+		.expect("Failed to execute request.");
+
+	assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn malformed_payloads_are_rejected_with_a_400() {
+	let app = spawn_app().await;
+
+	let response = app
+		.post_postmark_webhook(&serde_json::json!({"RecordType": "Bounce"}))
+		.await;
+
+	assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_hard_bounce_marks_the_subscriber_as_bounced() {
+	let app = spawn_app().await;
+	let email = confirmed_subscriber_email(&app).await;
+
+	let response = app.post_postmark_webhook(&bounce(&email, "HardBounce")).await;
+
+	assert_eq!(response.status().as_u16(), 200);
+	assert_eq!(subscriber_status(&app, &email).await, "bounced");
+	let event = sqlx::query!("SELECT record_type, message_id, occurred_at FROM email_events WHERE email = $1", email)
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch the email event.");
+	assert_eq!(event.record_type, "bounce");
+	assert_eq!(event.message_id.as_deref(), Some("883953f4-6105-42a2-a16a-77a8eac79483"));
+	assert!(event.occurred_at.is_some());
+	let suppression_reason = sqlx::query_scalar!("SELECT reason FROM suppressed_addresses WHERE email = lower($1)", email)
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("The address was not suppressed.");
+	assert_eq!(suppression_reason, "Hard bounce reported by Postmark");
+}
+
+#[tokio::test]
+async fn a_soft_bounce_is_recorded_but_keeps_the_subscriber_confirmed() {
+	let app = spawn_app().await;
+	let email = confirmed_subscriber_email(&app).await;
+
+	let response = app.post_postmark_webhook(&bounce(&email, "SoftBounce")).await;
+
+	assert_eq!(response.status().as_u16(), 200);
+	assert_eq!(subscriber_status(&app, &email).await, "confirmed");
+	let n_events = sqlx::query_scalar!("SELECT COUNT(*) FROM email_events WHERE email = $1", email)
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+	assert_eq!(n_events, Some(1));
+}
+
+#[tokio::test]
+async fn a_spam_complaint_marks_the_subscriber_as_complained() {
+	let app = spawn_app().await;
+	let email = confirmed_subscriber_email(&app).await;
+
+	let response = app.post_postmark_webhook(&spam_complaint(&email)).await;
+
+	assert_eq!(response.status().as_u16(), 200);
+	assert_eq!(subscriber_status(&app, &email).await, "complained");
+}
+
+#[tokio::test]
+async fn a_later_bounce_does_not_override_a_spam_complaint() {
+	let app = spawn_app().await;
+	let email = confirmed_subscriber_email(&app).await;
+
+	app.post_postmark_webhook(&spam_complaint(&email)).await;
+	app.post_postmark_webhook(&bounce(&email, "HardBounce")).await;
+
+	assert_eq!(subscriber_status(&app, &email).await, "complained");
+}
+
+#[tokio::test]
+async fn deliveries_are_recorded() {
+	let app = spawn_app().await;
+	let email = confirmed_subscriber_email(&app).await;
+
+	let response = app
+		.post_postmark_webhook(&serde_json::json!({
+			"RecordType": "Delivery",
+			"MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+			"Recipient": email,
+			"DeliveredAt": "2024-04-15T16:33:54.9070259Z",
+			"Details": "Test delivery webhook details",
+		}))
+		.await;
+
+	assert_eq!(response.status().as_u16(), 200);
+	let event = sqlx::query!("SELECT record_type, payload FROM email_events WHERE email = $1", email)
+		.fetch_one(&app.connection_pool)
+		.await
+		.expect("Failed to fetch the email event.");
+	assert_eq!(event.record_type, "delivery");
+	assert_eq!(event.payload["Details"], "Test delivery webhook details");
+	assert_eq!(subscriber_status(&app, &email).await, "confirmed");
+}
+
+#[tokio::test]
+async fn untracked_record_types_are_acknowledged() {
+	let app = spawn_app().await;
+
+	let response = app
+		.post_postmark_webhook(&serde_json::json!({"RecordType": "Open", "Recipient": "ursula@example.com"}))
+		.await;
+
+	assert_eq!(response.status().as_u16(), 200);
+	let n_events = sqlx::query_scalar!("SELECT COUNT(*) FROM email_events")
+		.fetch_one(&app.connection_pool)
+		.await
+		.unwrap();
+	assert_eq!(n_events, Some(0));
+}
+
+#[tokio::test]
+async fn bounced_subscribers_are_excluded_from_newsletters() {
+	let app = spawn_app().await;
+	let email = confirmed_subscriber_email(&app).await;
+	app.post_postmark_webhook(&bounce(&email, "HardBounce")).await;
+
+	Mock::given(any())
+		.respond_with(ResponseTemplate::new(200))
+		.expect(0)
+		.mount(&app.email_server)
+		.await;
+	app.test_user.login(&app).await;
+	app.post_newsletters(&serde_json::json!({
+		"title": "Newsletter title",
+		"text_content": "Newsletter body as plain text",
+		"html_content": "<p>Newsletter body as HTML</p>",
+	}))
+	.await;
+	app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn signing_up_again_with_a_bounced_address_sends_no_email() {
+	let app = spawn_app().await;
+	let email = confirmed_subscriber_email(&app).await;
+	app.post_postmark_webhook(&bounce(&email, "HardBounce")).await;
+
+	Mock::given(path("/email"))
+		.and(method("POST"))
+		.respond_with(ResponseTemplate::new(200))
+		.expect(0)
+		.mount(&app.email_server)
+		.await;
+	let body = serde_urlencoded::to_string([("name", "le guin"), ("email", email.as_str())]).unwrap();
+	let response = app.post_subscriptions(body).await;
+	app.dispatch_outbox().await;
+
+	assert_eq!(response.status().as_u16(), 200);
+	assert_eq!(subscriber_status(&app, &email).await, "bounced");
+}